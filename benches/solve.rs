@@ -0,0 +1,38 @@
+//! Compares the cost per call of solving with and without cached contexts.
+//!
+//! Run with `cargo bench`.
+
+use std::time::{Duration, Instant};
+
+use caso::code::{Solver, Square};
+use caso::parsing::Parser;
+
+const N: u32 = 200;
+const EXPR: &str = "(A <-> B)[(A <-> C) -> (B <-> D)] <=> (C -> D)";
+
+fn bench(name: &str, mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..N {f()}
+    let per_call = start.elapsed() / N;
+    println!("{:<28} {:>10.1?}/call", name, per_call);
+    per_call
+}
+
+fn main() {
+    let uncached = bench("parse (new parser)", || {
+        Parser::new().unwrap().parse(EXPR).unwrap();
+    });
+    let cached = bench("parse (shared parser)", || {
+        Parser::shared().unwrap().parse(EXPR).unwrap();
+    });
+    println!("speedup: {:.1}x\n", uncached.as_secs_f64() / cached.as_secs_f64());
+
+    let sq = Square::new(&EXPR.try_into().unwrap()).unwrap();
+    let uncached = bench("eval (new solver)", || {
+        Solver::new().unwrap().eval(&sq).unwrap();
+    });
+    let cached = bench("eval (shared solver)", || {
+        Solver::shared().unwrap().eval(&sq).unwrap();
+    });
+    println!("speedup: {:.1}x", uncached.as_secs_f64() / cached.as_secs_f64());
+}
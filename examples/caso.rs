@@ -1,6 +1,22 @@
-use caso::solve_str;
+use caso::code::Solver;
+use caso::session::Session;
 
 fn main() {
+    let mut solver = match solver_from_args() {
+        Ok(x) => x,
+        Err(err) => {
+            eprintln!("ERROR:\n{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // Characters used to draw squares as box diagrams, if enabled.
+    let mut view: Option<&caso::render::Style> = None;
+    // Whether to display results using Unicode arrows.
+    let mut unicode = false;
+    // Hypotheses used by every solved expression.
+    let mut session = Session::new();
+
     println!("=== Caso 0.2 ===");
     println!("Type `help` for more information.");
     loop {
@@ -17,18 +33,124 @@ fn main() {
             }
         };
 
-        match &*input.trim() {
+        match input.trim() {
             "bye" => break,
             "help" => {print_help(); continue}
+            "view" => {
+                view = if view.is_some() {None} else {Some(&caso::render::UNICODE)};
+                continue;
+            }
+            "unicode" => {unicode = !unicode; continue}
+            "strict" => {solver.strict = !solver.strict; continue}
+            "view ascii" => {view = Some(&caso::render::ASCII); continue}
+            "view unicode" => {view = Some(&caso::render::UNICODE); continue}
+            "view line" => {view = None; continue}
+            "rules" => {
+                println!("{} rules and facts", solver.rules.len());
+                continue;
+            }
+            "rules reset" => {
+                solver = Solver {strict: solver.strict, ..Solver::shared().unwrap().clone()};
+                continue;
+            }
+            x if x.starts_with("rules add ") => {
+                if let Err(err) = solver.extend_file(x[10..].trim()) {
+                    println!("ERROR:\n{}", err);
+                }
+                continue;
+            }
+            x if x.starts_with("rules replace ") => {
+                match Solver::from_file(x[14..].trim()) {
+                    Ok(x) => solver = Solver {strict: solver.strict, ..x},
+                    Err(err) => println!("ERROR:\n{}", err),
+                }
+                continue;
+            }
+            x if x.starts_with("explain ") => {
+                let x = x[8..].trim();
+                match explain(&session, &solver, x) {
+                    Ok(()) => continue,
+                    Err(err) => {
+                        println!("ERROR:\n{}", err.render(x));
+                        continue;
+                    }
+                }
+            }
+            "hypotheses" => {
+                for (i, e) in session.hypotheses.iter().enumerate() {println!("{}: {}", i, e)}
+                continue;
+            }
+            x if x.starts_with("assume ") => {
+                let x = x[7..].trim();
+                let n = session.hypotheses.len();
+                match session.assume(&solver, x) {
+                    Ok(y) => for (i, e) in y.iter().enumerate() {println!("{}: {}", n + i, e)},
+                    Err(err) => println!("ERROR:\n{}", err.render(x)),
+                }
+                continue;
+            }
+            x if x.starts_with("retract ") => {
+                match x[8..].trim().parse().ok().and_then(|i| session.retract(i)) {
+                    Some(e) => println!("retracted: {}", e),
+                    None => println!("ERROR:\nExpected index of hypothesis, see `hypotheses`"),
+                }
+                continue;
+            }
+            x if x.starts_with("save ") => {
+                if let Err(err) = session.save(x[5..].trim()) {
+                    println!("ERROR:\n{}", err);
+                }
+                continue;
+            }
+            x if x.starts_with("load ") => {
+                match Session::load(x[5..].trim()) {
+                    Ok(x) => session = x,
+                    Err(err) => println!("ERROR:\n{}", err),
+                }
+                continue;
+            }
+            x if x.starts_with("? ") => {
+                let x = x[2..].trim();
+                match session.query_str(&solver, x) {
+                    Ok(y) => print!("{}", y),
+                    Err(err) => println!("ERROR:\n{}", err.render(x)),
+                }
+                continue;
+            }
+            x if x.starts_with("dot knowledge ") => {
+                let x = x[14..].trim();
+                match dot(&session, &solver, x, true) {
+                    Ok(y) => print!("{}", y),
+                    Err(err) => println!("ERROR:\n{}", err.render(x)),
+                }
+                continue;
+            }
+            x if x.starts_with("dot ") => {
+                let x = x[4..].trim();
+                match dot(&session, &solver, x, false) {
+                    Ok(y) => print!("{}", y),
+                    Err(err) => println!("ERROR:\n{}", err.render(x)),
+                }
+                continue;
+            }
+            x if x.starts_with("tikz ") => {
+                let x = x[5..].trim();
+                match tikz(&session, &solver, x) {
+                    Ok(y) => print!("{}", y),
+                    Err(err) => println!("ERROR:\n{}", err.render(x)),
+                }
+                continue;
+            }
             x if x.starts_with("echo ") => {
-                match caso::parsing::parse_str(x[5..].trim()) {
+                let x = x[5..].trim();
+                match caso::parsing::parse_str(x) {
                     Ok(x) => {
                         println!("{}", x);
                         println!("{:?}", x);
                         continue;
                     }
                     Err(err) => {
-                        println!("ERROR:\n{}", err);
+                        println!("ERROR:\n{}", err.render(x));
                         continue;
                     }
                 }
@@ -40,13 +162,107 @@ fn main() {
                 continue;
             }
             x => {
-                match solve_str(x) {
-                    Ok(y) => println!("{}", y),
-                    Err(err) => eprintln!("{}", err),
+                match session.solve(&solver, x) {
+                    Ok(y) => match y.diagnostics.iter().find(|err| !matches!(err, caso::Error::Uninferred(_))) {
+                        Some(err) => eprintln!("ERROR:\n{}", err.render(x)),
+                        None => {
+                            if let Some(style) = view {print_box(&y, style)}
+                            if unicode {println!("{}", y.unicode())} else {println!("{}", y)}
+                            if !y.corrections.is_empty() {println!("{}", highlight(&y, unicode))}
+                            // Corrected edges and edges that could not be inferred are reported as notes.
+                            for c in &y.corrections {println!("NOTE: {}", c)}
+                            for err in &y.diagnostics {println!("NOTE: {}", err)}
+                        }
+                    }
+                    Err(err) => eprintln!("ERROR:\n{}", err.render(x)),
                 }
             }
         }
     }
 }
 
+// Creates solver using rule files given by `--rules <file>` or `--replace-rules <file>`.
+fn solver_from_args() -> Result<Solver, String> {
+    let mut solver = Solver::shared().map_err(|err| err.to_string())?.clone();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (&*arg, args.next()) {
+            ("--rules", Some(file)) => solver.extend_file(file).map_err(|err| err.to_string())?,
+            ("--replace-rules", Some(file)) =>
+                solver = Solver::from_file(file).map_err(|err| err.to_string())?,
+            _ => return Err(format!("Unknown argument `{}`", arg)),
+        }
+    }
+    Ok(solver)
+}
+
+fn explain(session: &Session, solver: &Solver, x: &str) -> Result<(), caso::Error> {
+    use caso::code::EDGES;
+
+    let (d, n, explanations) = session.explain(solver, x)?;
+    if explanations.is_empty() {
+        println!("No edges were upgraded");
+    }
+    for e in &explanations {
+        // Edges of a single face are named by their position in the face.
+        match &d.faces[n..] {
+            [face] => match face.edges.iter().position(|&(k, _)| k == e.edge) {
+                Some(i) => print!("{}: {}", EDGES[i], e),
+                None => print!("edge {}: {}", e.edge, e),
+            },
+            _ => print!("edge {}: {}", e.edge, e),
+        }
+    }
+    Ok(())
+}
+
+// Exports diagram with hypotheses in Graphviz DOT format.
+fn dot(session: &Session, solver: &Solver, x: &str, knowledge: bool) -> Result<String, caso::Error> {
+    let (d, _, _) = session.diagram(caso::parsing::Parser::shared()?.parse_faces(x)?)?;
+    Ok(caso::dot::export(solver, &d, knowledge))
+}
+
+// Solves squares using hypotheses and exports them as tikz-cd.
+fn tikz(session: &Session, solver: &Solver, x: &str) -> Result<String, caso::Error> {
+    let y = session.solve(solver, x)?;
+    if let Some(err) = y.diagnostics.iter().find(|err| !matches!(err, caso::Error::Uninferred(_))) {
+        return Err(err.clone());
+    }
+    y.faces.iter().map(caso::tikz::expr).collect()
+}
+
+// Draws solved squares as box diagrams, if possible.
+fn print_box(y: &caso::code::Solution, style: &caso::render::Style) {
+    use caso::code::Square;
+
+    for face in &y.faces {
+        if let Ok(z) = Square::new(face).and_then(|sq| caso::render::square(&sq, style)) {
+            print!("{}", z);
+        }
+    }
+}
+
+// Returns a caret line pointing at corrected edges in the displayed solution.
+fn highlight(y: &caso::code::Solution, unicode: bool) -> String {
+    let show = |e: &caso::Expr| if unicode {e.unicode().to_string()} else {e.to_string()};
+    let mut line: Vec<char> = vec![];
+    let mut offset = 0;
+    for (i, face) in y.faces.iter().enumerate() {
+        let x = show(face);
+        let edges = caso::code::face_edges(face);
+        for c in y.corrections.iter().filter(|c| c.face == i) {
+            let e = format!("({})", show(&edges[c.edge]));
+            if let Some(pos) = x.find(&e) {
+                let col = offset + x[..pos].chars().count();
+                let end = col + e.chars().count();
+                if line.len() < end {line.resize(end, ' ')}
+                for ch in &mut line[col..end] {*ch = '^'}
+            }
+        }
+        // Faces are separated by `; `.
+        offset += x.chars().count() + 2;
+    }
+    line.into_iter().collect()
+}
+
 fn print_help() {print!("{}", include_str!("../assets/help/help.txt"))}
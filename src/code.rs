@@ -1,8 +1,18 @@
 //! Commutative diagram solver.
 
-use crate::Expr;
+use crate::{Error, Expr};
+use crate::error::Shape;
+use crate::parsing::Parser;
 use crate::Morphism::{self, *};
 use crate::sym;
+use crate::proof::{Answer, Explanation, Step, Tracer};
+
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Names of the edges of a square, in the order they are stored.
+pub const EDGES: [&str; 4] = ["left", "top", "bottom", "right"];
 
 /// Normalize square.
 pub fn eval(code: [Morphism; 4]) -> [Morphism; 4] {
@@ -21,7 +31,7 @@ pub fn eval(code: [Morphism; 4]) -> [Morphism; 4] {
         }
     }
 
-    let ref mut x = [Dir, RevDir];
+    let x = &mut [Dir, RevDir];
     if (
         read(x, code[0], false) &&
         or([x[1], Iso], code[1]) &&
@@ -48,7 +58,8 @@ pub fn eval(code: [Morphism; 4]) -> [Morphism; 4] {
     ) {[Iso; 4]} else {code}
 }
 
-fn is_reversed(code: Morphism) -> bool {
+/// Returns `true` if the morphism points from right to left.
+pub fn is_reversed(code: Morphism) -> bool {
     match code {
         Dir | Mono | Epi | EpiMono | RightInv | Iso => false,
         RevDir | RevMono | RevEpi | RevEpiMono | RevRightInv | RevIso | RevZero => true,
@@ -56,7 +67,8 @@ fn is_reversed(code: Morphism) -> bool {
     }
 }
 
-fn reverse(code: Morphism) -> Morphism {
+/// Returns the same morphism pointing in the opposite direction.
+pub fn reverse(code: Morphism) -> Morphism {
     match code {
         Unknown => Unknown,
         Dir => RevDir,
@@ -76,8 +88,126 @@ fn reverse(code: Morphism) -> Morphism {
     }
 }
 
+/// Avatars of relations that can be reintegrated into morphisms.
+pub const AVATARS: [&str; 7] = ["mor", "mono", "epi", "iso", "left_inv", "right_inv", "zero"];
+
+/// Avatars of relations describing compositions, see [Solver::expand].
+pub const COMPOSITION: [&str; 3] = ["first", "middle", "last"];
+
+/// Avatar of relations describing contradictions, e.g. `(X, contradiction'(Y))`.
+///
+/// Rules deriving such a relation declare facts that can not hold together,
+/// which the solver reports as [Error::Inconsistent].
+pub const CONTRADICTION: &str = "contradiction";
+
+/// Returns the avatars of relations describing a morphism, e.g. `["epi", "mono"]` for `!->>`.
+///
+/// The relations point in the direction of the morphism, see [is_reversed].
+pub fn avatars(code: Morphism) -> &'static [&'static str] {
+    match code {
+        Unknown => &[],
+        Dir | RevDir => &["mor"],
+        Iso | RevIso => &["iso"],
+        Mono | RevMono => &["mono"],
+        Zero | RevZero => &["zero"],
+        Epi | RevEpi => &["epi"],
+        EpiMono | RevEpiMono => &["epi", "mono"],
+        RightInv | RevRightInv => &["right_inv"],
+    }
+}
+
+// Properties of a morphism pointing from left to right, see [upgrade].
+const MONO: u8 = 1;
+const EPI: u8 = 2;
+const RIGHT_INV: u8 = 4;
+const ISO: u8 = 8;
+const ZERO: u8 = 16;
+
+// Returns the properties of a morphism, ignoring its direction.
+fn properties(code: Morphism) -> u8 {
+    match code {
+        Unknown | Dir => 0,
+        Mono => MONO,
+        Epi => EPI,
+        EpiMono => MONO | EPI,
+        RightInv => EPI | RIGHT_INV,
+        Iso => MONO | EPI | RIGHT_INV | ISO,
+        Zero => MONO | EPI | RIGHT_INV | ISO | ZERO,
+        x => properties(reverse(x)),
+    }
+}
+
+// Adds the properties implied by others.
+fn close(mut p: u8) -> u8 {
+    if p & ZERO != 0 {p |= ISO}
+    // A mono with a right inverse is iso.
+    if p & (MONO | RIGHT_INV) == MONO | RIGHT_INV {p |= ISO}
+    if p & ISO != 0 {p |= MONO | EPI | RIGHT_INV}
+    if p & RIGHT_INV != 0 {p |= EPI}
+    p
+}
+
+// Returns the strongest morphism pointing from left to right with closed properties.
+fn from_properties(p: u8) -> Morphism {
+    if p & ZERO != 0 {Zero}
+    else if p & ISO != 0 {Iso}
+    else if p & RIGHT_INV != 0 {RightInv}
+    else if p & (MONO | EPI) == MONO | EPI {EpiMono}
+    else if p & MONO != 0 {Mono}
+    else if p & EPI != 0 {Epi}
+    else {Dir}
+}
+
+/// Upgrades a morphism using a relation derived by the solver.
+///
+/// The relation is given by its avatar, e.g. `mono` for `(X, mono'(Y))`.
+/// The result is the join of the morphism and the relation,
+/// such that upgrading with several relations gives the same result in any order.
+/// A morphism with a left inverse, `left_inv`, is upgraded to mono.
+/// The relation must point in the direction of the morphism, see [avatars],
+/// e.g. `(D, mono'(C))` upgrades `C <- D` to `C <-! D`.
+/// Unknown morphisms are kept.
+/// Returns an error if the avatar is not one of [AVATARS].
+pub fn upgrade(code: Morphism, av: &str) -> Result<Morphism, Error> {
+    let p = match av {
+        "mor" => 0,
+        "mono" | "left_inv" => MONO,
+        "epi" => EPI,
+        "right_inv" => RIGHT_INV,
+        "iso" => ISO,
+        "zero" => ZERO,
+        _ => return Err(Error::Unsupported {morphism: code, avatar: Arc::new(av.into())}),
+    };
+    if code == Unknown {return Ok(code)}
+    let res = from_properties(close(properties(code) | p));
+    Ok(if is_reversed(code) {reverse(res)} else {res})
+}
+
+/// Stores knowledge derived by the solver.
+#[derive(Clone, Debug)]
+pub struct Knowledge {
+    /// Rules and facts, in the order they were added.
+    pub facts: Vec<avalog::Expr<sym::Sym>>,
+    /// The number of rules and facts from the rule base.
+    pub axioms: usize,
+    /// The number of rules and facts before solving.
+    pub premises: usize,
+}
+
+// Records an upgrade of an edge caused by a fact.
+struct Upgrade {
+    edge: usize,
+    from: Morphism,
+    to: Morphism,
+    fact: usize,
+}
+
 /// Represents a commutative square.
+///
+/// A composition, e.g. `A -> B -> C`, is an edge from its source to its target.
+/// Use [Diagram] to also solve the morphisms of compositions.
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Square {
     /// Bound expressions.
     pub bind: Vec<Expr>,
@@ -88,62 +218,281 @@ pub struct Square {
     pub labels: [[u8; 3]; 4],
     /// Morphism codes of the edges.
     pub code: [Morphism; 4],
+    /// Names of the edges, e.g. `f` in `f : A -> B`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub names: [Option<Arc<String>>; 4],
 }
 
-impl Square {
-    /// Creates a new square.
-    pub fn new(expr: &Expr) -> Option<Self> {
-        fn find(bind: &mut Vec<Expr>, a: &Expr) -> u8 {
-            for (i, e) in bind.iter().enumerate() {
-                if e == a {return (i + 1) as u8}
-            }
-            bind.push(a.clone());
-            bind.len() as u8
-        }
-        fn new(bind: &mut Vec<Expr>, a: &Expr) -> [u8; 3] {
-            match a {
-                Mor(_, _, b) => {
-                    let n = find(bind, &b.0);
-                    let m = find(bind, &b.1);
-                    [0, n, m]
+// Finds index of bound expression, binding it if necessary.
+fn find(bind: &mut Vec<Expr>, a: &Expr) -> u8 {
+    for (i, e) in bind.iter().enumerate() {
+        if e == a {return (i + 1) as u8}
+    }
+    bind.push(a.clone());
+    bind.len() as u8
+}
+
+// Creates label of an edge.
+fn new(bind: &mut Vec<Expr>, a: &Expr) -> [u8; 3] {
+    use crate::Expr::*;
+
+    match a {
+        Mor(_, _, b) => {
+            let n = find(bind, &b.0);
+            let m = find(bind, &b.1);
+            [0, n, m]
+        }
+        _ => [find(bind, a), 0, 0],
+    }
+}
+
+// Returns the morphism from source to target of a composition, in the direction it is written.
+fn composite(e: &Expr) -> Result<Morphism, Error> {
+    match e.unnamed() {
+        Expr::Mor(mor, _, _) => Ok(*mor),
+        Expr::Comp(gf) if gf.1.target() == gf.0.source() => {
+            compose(composite(&gf.1)?, composite(&gf.0)?).ok_or(Error::Shape(Shape::Composition))
+        }
+        _ => Err(Error::Shape(Shape::Composition)),
+    }
+}
+
+// Returns edge without name, replacing a composition by a morphism from source to target.
+fn span(e: &Expr) -> Result<Expr, Error> {
+    match e.unnamed() {
+        Expr::Comp(_) => {
+            let ab = (e.source().clone(), e.target().clone());
+            Ok(Expr::Mor(composite(e)?, 1, Arc::new(ab)))
+        }
+        x => Ok(x.clone()),
+    }
+}
+
+// Collects the morphisms of compositions, in the order they are written,
+// and every composition as `[<first>, <middle>, <last>]` in the direction of its morphism.
+fn parts(e: &Expr, res: &mut Vec<Expr>, comps: &mut Vec<[Expr; 3]>) {
+    use crate::Expr::*;
+
+    match e {
+        Mor(_, _, a) | Path(a) => {
+            parts(&a.0, res, comps);
+            parts(&a.1, res, comps);
+        }
+        Named(_, a) if matches!(a.unnamed(), Comp(_)) => parts(a, res, comps),
+        Comp(gf) => {
+            for x in [&gf.1, &gf.0] {
+                match x.unnamed() {
+                    Comp(_) => parts(x, res, comps),
+                    Mor(_, _, _) => res.push(x.clone()),
+                    _ => {}
                 }
-                _ => [find(bind, a), 0, 0],
+            }
+            if let Ok(code) = composite(e) {
+                let (a, b, c) = (e.source().clone(), gf.1.target().clone(), e.target().clone());
+                comps.push(if is_reversed(code) {[c, b, a]} else {[a, b, c]});
             }
         }
-        fn code(a: &Expr, edge: usize, labels: &mut [[u8; 3]; 4]) -> Morphism {
-            let f = (
-                edge == 1 &&
-                labels[0][1] != 0 &&
-                labels[edge][2] == labels[0][1]
-            ) || (
-                edge == 2 &&
-                labels[0][2] != 0 &&
-                labels[edge][2] == labels[0][2]
-            ) || (
-                edge == 3 &&
-                labels[1][2] != 0 &&
-                labels[edge][2] == labels[1][2]
-            ) || (
-                edge == 3 &&
-                labels[2][2] != 0 &&
-                labels[edge][1] == labels[2][2]
-            );
-
-            if let Mor(mor, _, b) = a {
-                match mor {
-                    Unknown => Unknown,
-                    Dir | Mono | Epi if b.0 == b.1 => Iso,
-                    x if edge == 0 => *x,
-                    x => if f {
-                        // Swap end-points to match morphism.
-                        let [a, b, c] = labels[edge];
-                        labels[edge] = [a, c, b];
-                        reverse(*x)
-                    } else {*x},
+        _ => {}
+    }
+}
+
+// Sets morphisms of compositions, in the order they are written.
+fn parts_mut(e: &mut Expr, f: &mut impl FnMut(&mut Morphism, &mut Arc<(Expr, Expr)>)) {
+    use crate::Expr::*;
+
+    match e {
+        Mor(_, _, a) | Path(a) => {
+            let a = Arc::make_mut(a);
+            parts_mut(&mut a.0, f);
+            parts_mut(&mut a.1, f);
+        }
+        Named(_, a) if matches!(a.unnamed(), Comp(_)) => parts_mut(Arc::make_mut(a), f),
+        Comp(gf) => {
+            let gf = Arc::make_mut(gf);
+            for x in [&mut gf.1, &mut gf.0] {
+                if let Comp(_) = x.unnamed() {
+                    parts_mut(x, f)
+                } else if let Some((mor, ab)) = mor_mut(x) {
+                    f(mor, ab)
                 }
-            } else {Unknown}
+            }
+        }
+        _ => {}
+    }
+}
+
+// Corners connected by each edge of a square.
+const SQUARE_ENDS: [(usize, usize); 4] = [(0, 1), (0, 2), (1, 3), (2, 3)];
+// Corners connected by each edge of a triangle.
+const TRIANGLE_ENDS: [(usize, usize); 3] = [(0, 1), (0, 2), (1, 2)];
+
+// Returns the bound corners of a square or triangle, using edges that are morphisms.
+fn corners(labels: &[[u8; 3]]) -> Option<Vec<u8>> {
+    let ends: &[(usize, usize)] = if labels.len() == 4 {&SQUARE_ENDS} else {&TRIANGLE_ENDS};
+    let mut res = vec![0; labels.len()];
+    for (&label, &(a, b)) in labels.iter().zip(ends) {
+        if label[0] != 0 {continue}
+        for (corner, ind) in [(a, label[1]), (b, label[2])] {
+            if res[corner] != 0 && res[corner] != ind {return None}
+            res[corner] = ind;
+        }
+    }
+    if res.contains(&0) {None} else {Some(res)}
+}
+
+// Returns the morphism of an edge of a square or triangle.
+//
+// Swaps the end-points of the edge when they point against an edge before it,
+// e.g. when the target of the top edge is the source of the left edge.
+// The left edge is never swapped.
+fn edge_code(a: &Expr, edge: usize, labels: &mut [[u8; 3]]) -> Morphism {
+    let ends: &[(usize, usize)] = if labels.len() == 4 {&SQUARE_ENDS} else {&TRIANGLE_ENDS};
+    let (s, t) = ends[edge];
+    let [_, x, y] = labels[edge];
+    // Returns the object of an edge before at a corner, if known.
+    let corner = |j: usize, c: usize| match ends[j] {
+        (a, _) if a == c && labels[j][1] != 0 => Some(labels[j][1]),
+        (_, b) if b == c && labels[j][2] != 0 => Some(labels[j][2]),
+        _ => None,
+    };
+    let f = (0..edge).any(|j| corner(j, s) == Some(y) || corner(j, t) == Some(x));
+
+    if let Expr::Mor(mor, _, b) = a {
+        match mor {
+            Unknown => Unknown,
+            Dir | Mono | Epi if b.0 == b.1 => Iso,
+            x if edge == 0 => *x,
+            x => if f {
+                // Swap end-points to match morphism.
+                let [a, b, c] = labels[edge];
+                labels[edge] = [a, c, b];
+                reverse(*x)
+            } else {*x},
+        }
+    } else {Unknown}
+}
+
+// Returns `true` if edges of a square or triangle meet at the same objects in every corner,
+// when each edge may be written in either direction.
+fn connects(labels: &[[u8; 3]], edges: &[usize]) -> bool {orient(labels, edges).is_some()}
+
+// Returns the bound corners where edges of a square or triangle meet,
+// when each edge may be written in either direction.
+//
+// Corners that are not known from any of the edges are 0.
+fn orient(labels: &[[u8; 3]], edges: &[usize]) -> Option<Vec<u8>> {
+    let ends: &[(usize, usize)] = if labels.len() == 4 {&SQUARE_ENDS} else {&TRIANGLE_ENDS};
+    (0..1u32 << edges.len()).find_map(|flip| {
+        let mut res = vec![0; labels.len()];
+        edges.iter().enumerate().all(|(k, &i)| {
+            let [_, a, b] = labels[i];
+            let (a, b) = if flip >> k & 1 == 1 {(b, a)} else {(a, b)};
+            [(ends[i].0, a), (ends[i].1, b)].into_iter().all(|(corner, ind)| {
+                if res[corner] == 0 {res[corner] = ind}
+                res[corner] == ind
+            })
+        }).then_some(res)
+    })
+}
+
+// Checks that edges of a face can meet at the same objects in every corner.
+//
+// Reports the smallest set of edges that can not meet.
+fn check_corners(face: usize, labels: &[[u8; 3]]) -> Result<(), Error> {
+    let mors: Vec<usize> = (0..labels.len()).filter(|&i| labels[i][0] == 0).collect();
+    if connects(labels, &mors) {return Ok(())}
+    let mut subsets: Vec<Vec<usize>> = (1..1u32 << mors.len())
+        .map(|mask| mors.iter().enumerate().filter(|&(k, _)| mask >> k & 1 == 1).map(|(_, &i)| i).collect())
+        .collect();
+    subsets.sort_by_key(|x| x.len());
+    let edges = subsets.into_iter().find(|x| !connects(labels, x)).unwrap_or(mors);
+    Err(Error::Contradiction {face, edges})
+}
+
+// Returns the bound end-points of an edge of a square or triangle.
+fn ends(labels: &[[u8; 3]], edge: usize) -> Option<[u8; 2]> {
+    let res = corners(labels)?;
+    let (a, b) = if labels.len() == 4 {SQUARE_ENDS[edge]} else {TRIANGLE_ENDS[edge]};
+    Some([res[a], res[b]])
+}
+
+// Returns the morphism inferred for an edge that is an object, e.g. `f : A -> B` for `f`.
+fn inferred(bind: &[Expr], obj: u8, [a, b]: [u8; 2], code: Morphism) -> Expr {
+    let ab = (bind[(a - 1) as usize].clone(), bind[(b - 1) as usize].clone());
+    let e = Expr::Mor(code, 1, Arc::new(ab));
+    match &bind[(obj - 1) as usize] {
+        Expr::Obj(name) => Expr::Named(name.clone(), Arc::new(e)),
+        _ => e,
+    }
+}
+
+// Returns an edge of a square or triangle, in the order of [EDGES].
+fn edge_mut(e: &mut Expr, i: usize) -> Option<&mut Expr> {
+    use crate::Expr::*;
+
+    match e {
+        Mor(Iso, 2, a) => {
+            let a = Arc::make_mut(a);
+            if i == 3 {Some(&mut a.1)} else {edge_mut(&mut a.0, i)}
+        }
+        Path(ltb) => {
+            let ltb = Arc::make_mut(ltb);
+            match (i, &mut ltb.1) {
+                (0, _) => Some(&mut ltb.0),
+                (1, Mor(_, _, tb)) => Some(&mut Arc::make_mut(tb).0),
+                (2, Mor(_, _, tb)) => Some(&mut Arc::make_mut(tb).1),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// Replaces edges that are objects with the morphisms inferred for them.
+fn rewrite_objects(bind: &[Expr], labels: &[[u8; 3]], new_code: &[Morphism], e: &mut Expr) {
+    for (i, &[obj, _, _]) in labels.iter().enumerate() {
+        if obj == 0 || new_code[i] == Unknown {continue}
+        if let (Some(ab), Some(x)) = (ends(labels, i), edge_mut(e, i)) {
+            *x = inferred(bind, obj, ab, new_code[i]);
         }
+    }
+}
+
+/// Implemented by diagrams whose edges can be evaluated by the solver.
+///
+/// Bound expressions, labels and morphism codes use the same format as [Square].
+pub trait Edges {
+    /// Returns bound expressions.
+    fn bind(&self) -> &[Expr];
+    /// Returns labels of edges.
+    fn labels(&self) -> &[[u8; 3]];
+    /// Returns morphism codes of edges.
+    fn code(&self) -> &[Morphism];
+    /// Returns compositions of morphisms `[<first>, <middle>, <last>]`,
+    /// where the first morphism goes from `<first>` to `<middle>`
+    /// and the second morphism from `<middle>` to `<last>`.
+    fn comps(&self) -> &[[u8; 3]] {&[]}
+    /// Returns the end-points of an edge that is an object,
+    /// when they are known from the other edges of its face.
+    fn ends(&self, _edge: usize) -> Option<[u8; 2]> {None}
+    /// Checks that the edges of every face can meet at the same objects in every corner,
+    /// when each edge may be written in either direction.
+    ///
+    /// Returns [Error::Contradiction] with the smallest set of conflicting edges.
+    fn check(&self) -> Result<(), Error> {Ok(())}
+    /// Returns the face of an edge and its index in the face, see [EDGES].
+    ///
+    /// Returns `None` if the edge is not part of any face.
+    fn face_edge(&self, edge: usize) -> Option<(usize, usize)> {Some((0, edge))}
+    /// The expression type the diagram was created from.
+    type Target: ?Sized;
+    /// Rewrites expression of the diagram using new morphism codes.
+    fn rewrite(&self, new_code: &[Morphism], e: &mut Self::Target);
+}
 
+impl Square {
+    /// Creates a new square.
+    pub fn new(expr: &Expr) -> Result<Self, Error> {
         use crate::Expr::*;
         use crate::Morphism::*;
 
@@ -152,129 +501,1033 @@ impl Square {
             if let Path(ltb) = &a.0 {
                 match &ltb.1 {
                     Mor(_, _, tb) => {
-                        let mut labels = [
-                            new(&mut bind, &ltb.0),
-                            new(&mut bind, &tb.0),
-                            new(&mut bind, &tb.1),
-                            new(&mut bind, &a.1)
-                        ];
-                        let code = [
-                            code(&ltb.0, 0, &mut labels),
-                            code(&tb.0, 1, &mut labels),
-                            code(&tb.1, 2, &mut labels),
-                            code(&a.1, 3, &mut labels),
-                        ];
-                        Some(Square {labels, bind, code})
+                        let edges = [&ltb.0, &tb.0, &tb.1, &a.1];
+                        let spans = [span(&ltb.0)?, span(&tb.0)?, span(&tb.1)?, span(&a.1)?];
+                        let mut labels = spans.each_ref().map(|e| new(&mut bind, e));
+                        let code = std::array::from_fn(|i| edge_code(&spans[i], i, &mut labels));
+                        let names = edges.map(|e| e.name().cloned());
+                        Ok(Square {labels, bind, code, names})
                     }
-                    _ => None,
+                    _ => Err(Error::Shape(Shape::MissingArrow)),
                 }
             } else {
-                None
+                Err(Error::Shape(Shape::MissingPath))
             }
         } else {
-            None
+            Err(Error::Shape(Shape::MissingIso))
         }
     }
 
     /// Evaluates square.
+    ///
+    /// Returns the original morphisms if the solver failed.
     pub fn eval(&self) -> [Morphism; 4] {
-        fn update_code(code: Morphism, av: &Arc<String>) -> Morphism {
-            match (code, &***av) {
-                (Dir, "mor") => code,
-                (Dir, "mono") => Mono,
-                (Dir, "epi") => Epi,
-                (Dir, "zero") => Zero,
-                (Dir, "left_inv") => code,
-                (Dir, "right_inv") => RightInv,
-                (RevDir, "mor") => code,
-                (RevDir, "mono") => RevMono,
-                (RevDir, "epi") => RevEpi,
-                (RevDir, "iso") => RevIso,
-                (RevDir, "zero") => RevZero,
-                (RevDir, "left_inv") => RevRightInv,
-                (RevDir, "right_inv") => code,
-                (Mono, "mono" | "mor") => code,
-                (Mono, "epi") => EpiMono,
-                (Mono, "zero") => Zero,
-                (RevMono, "mor") => code,
-                (RevMono, "mono") => code,
-                (RevMono, "epi") => RevEpiMono,
-                (RevMono, "zero") => RevZero,
-                (Epi, "epi" | "mor") => code,
-                (Epi, "left_inv" | "right_inv") => code,
-                (Epi, "mono") => EpiMono,
-                (Epi, "iso") => Iso,
-                (RevEpi, "epi" | "mor") => code,
-                (RevEpi, "mono") => RevEpiMono,
-                (RevEpi, "right_inv") => RevRightInv,
-                (RevEpi, "iso") => RevIso,
-                (EpiMono, "epi" | "mono") => code,
-                (EpiMono, "left_inv" | "right_inv") => code,
-                (EpiMono, "iso") => Iso,
-                (RevEpiMono, "mor" | "epi" | "mono") => code,
-                (RevEpiMono, "left_inv" | "right_inv") => code,
-                (RevEpiMono, "iso") => RevIso,
-                (RightInv, "mor" | "epi" | "right_inv") => code,
-                (RightInv, "mono") => code,
-                (RightInv, "left_inv" | "iso") => Iso,
-                (RevRightInv, "mor" | "epi" | "right_inv") => code,
-                (RevRightInv, "mono") => code,
-                (RevRightInv, "left_inv" | "iso") => RevIso,
-                (Iso, "iso" | "left_inv" | "right_inv" | "mono" | "epi" | "mor") => code,
-                (Iso, "zero") => Zero,
-                (RevIso, "iso" | "left_inv" | "right_inv" | "mono" | "epi" | "mor") => code,
-                (RevIso, "zero") => RevZero,
-                (Zero, "zero" | "iso" | "left_inv" | "right_inv" | "mono" | "epi" | "mor") => code,
-                (RevZero, "zero" | "iso" | "left_inv" | "right_inv" | "mono" | "epi" | "mor") => code,
-                _ => unimplemented!("{:?} {}", code, av),
+        self.try_eval().unwrap_or(self.code)
+    }
+
+    /// Evaluates square, reporting an error if the solver failed.
+    ///
+    /// Relations that can not be represented by the morphism of an edge
+    /// are reported as [Error::Unsupported].
+    /// Edges that can not meet at the corners are reported as [Error::Contradiction].
+    pub fn try_eval(&self) -> Result<[Morphism; 4], Error> {
+        let new_code = Solver::shared()?.eval(self)?;
+        Ok(std::array::from_fn(|i| new_code[i]))
+    }
+
+    /// Explains why the solver upgraded edges of the square.
+    ///
+    /// Returns an explanation for every edge that changed,
+    /// with the chain of rules and input facts leading to each upgrade.
+    pub fn explain(&self) -> Result<Vec<Explanation>, Error> {
+        Solver::shared()?.explain(self)
+    }
+
+    /// Expands knowledge about the morphisms of the square.
+    pub fn knowledge(&self) -> Result<Knowledge, Error> {
+        Ok(Solver::shared()?.knowledge(&self.bind, &self.labels, &self.code))
+    }
+
+    /// Update square.
+    pub fn update(&self, e: &mut Expr) -> Result<(), Error> {
+        Solver::shared()?.update(self, e)
+    }
+
+    /// Returns the corners `[A, B, C, D]` of the square,
+    /// where the left edge goes from `A` to `B` and the right edge from `C` to `D`.
+    ///
+    /// Edges that are objects are allowed, as long as every corner is known from another edge.
+    /// Returns `None` if the edges do not connect.
+    pub fn corners(&self) -> Option<[&Expr; 4]> {
+        let res = corners(&self.labels)?;
+        Some(std::array::from_fn(|i| &self.bind[(res[i] - 1) as usize]))
+    }
+
+    /// Returns the canonical form of the square under its symmetries.
+    ///
+    /// The symmetries are the 8 ways of drawing the same square by rotating and reflecting it,
+    /// e.g. `(A -> B)[(A -> C) -> (B -> D)] <=> (C -> D)` transposed is
+    /// `(A -> C)[(A -> B) -> (C -> D)] <=> (B -> D)`.
+    /// Every edge is written in the direction of the corners, reversing its morphism if needed,
+    /// and the drawing with the smallest edges in the order of [EDGES] is picked.
+    /// Returns `None` if the edges do not connect.
+    pub fn canonical(&self) -> Option<Square> {
+        let mors: Vec<usize> = (0..4).filter(|&i| self.labels[i][0] == 0).collect();
+        let k = orient(&self.labels, &mors)?;
+        // Writes edge in the direction from one corner to another.
+        let edge = |i: usize, [a, b]: [usize; 2]| -> ([u8; 3], Morphism) {
+            let (label, code) = match self.labels[i] {
+                [0, x, y] if [x, y] != [k[a], k[b]] => ([0, y, x], reverse(self.code[i])),
+                label => (label, self.code[i]),
+            };
+            // Iso and zero hold in both directions.
+            (label, match code {RevIso => Iso, RevZero => Zero, x => x})
+        };
+        let bound = |i: u8| if i == 0 {None} else {Some(&self.bind[(i - 1) as usize])};
+
+        let mut best = None;
+        for p in symmetries() {
+            let mut labels = [[0; 3]; 4];
+            let mut code = [Unknown; 4];
+            let mut names: [Option<Arc<String>>; 4] = Default::default();
+            for (i, &(a, b)) in SQUARE_ENDS.iter().enumerate() {
+                let (a, b) = (p[a], p[b]);
+                let j = SQUARE_ENDS.iter().position(|&e| e == (a, b) || e == (b, a)).unwrap();
+                (labels[i], code[i]) = edge(j, [a, b]);
+                names[i] = self.names[j].clone();
+            }
+            let key: Vec<_> = (0..4).map(|i| (labels[i].map(bound), code[i], names[i].clone())).collect();
+            if best.as_ref().is_none_or(|(x, _)| key < *x) {best = Some((key, (labels, code, names)))}
+        }
+
+        let (_, (mut labels, code, names)) = best?;
+        // Bind expressions in the order they appear.
+        let mut bind = vec![];
+        for label in &mut labels {
+            for ind in label.iter_mut().filter(|ind| **ind != 0) {
+                *ind = find(&mut bind, &self.bind[(*ind - 1) as usize]);
+            }
+        }
+        Some(Square {bind, labels, code, names})
+    }
+
+    // Returns the expression of an edge, in the direction of the square.
+    fn edge(&self, i: usize) -> Expr {
+        match self.labels[i] {
+            [0, a, b] => {
+                let ab = (self.bind[(a - 1) as usize].clone(), self.bind[(b - 1) as usize].clone());
+                let e = Expr::Mor(self.code[i], 1, Arc::new(ab));
+                match &self.names[i] {
+                    Some(name) => Expr::Named(name.clone(), Arc::new(e)),
+                    None => e,
+                }
+            }
+            [obj, _, _] => self.bind[(obj - 1) as usize].clone(),
+        }
+    }
+
+    /// Pastes another square to the right, sharing the right edge of this square.
+    ///
+    /// Returns the outer square, or `None` if the squares do not share the edge
+    /// or the top or bottom edges can not be composed.
+    pub fn paste_horizontal(&self, other: &Square) -> Option<Expr> {
+        let [a, b, c, d] = self.corners()?;
+        let [a2, b2, c2, d2] = other.corners()?;
+        if (c, d) != (a2, b2) || self.code[3] != other.code[0] {return None}
+
+        let top = compose(self.code[1], other.code[1])?;
+        let bottom = compose(self.code[2], other.code[2])?;
+        Some(square(
+            self.edge(0),
+            Expr::Mor(top, 1, Arc::new((a.clone(), c2.clone()))),
+            Expr::Mor(bottom, 1, Arc::new((b.clone(), d2.clone()))),
+            other.edge(3),
+        ))
+    }
+
+    /// Pastes another square below, sharing the bottom edge of this square.
+    ///
+    /// Returns the outer square, or `None` if the squares do not share the edge
+    /// or the left or right edges can not be composed.
+    pub fn paste_vertical(&self, other: &Square) -> Option<Expr> {
+        let [a, b, c, d] = self.corners()?;
+        let [a2, b2, c2, d2] = other.corners()?;
+        if (b, d) != (a2, c2) || self.code[2] != other.code[1] {return None}
+
+        let left = compose(self.code[0], other.code[0])?;
+        let right = compose(self.code[3], other.code[3])?;
+        Some(square(
+            Expr::Mor(left, 1, Arc::new((a.clone(), b2.clone()))),
+            self.edge(1),
+            other.edge(2),
+            Expr::Mor(right, 1, Arc::new((c.clone(), d2.clone()))),
+        ))
+    }
+}
+
+// Returns the permutations of corners of a square that keep edges between the same corners.
+fn symmetries() -> impl Iterator<Item = [usize; 4]> {
+    (0..4 * 4 * 4 * 4).map(|n| [n % 4, n / 4 % 4, n / 16 % 4, n / 64])
+        .filter(|p: &[usize; 4]| (0..4).all(|i| p[..i].iter().all(|&x| x != p[i])))
+        .filter(|p| SQUARE_ENDS.iter().all(|&(a, b)| {
+            SQUARE_ENDS.iter().any(|&e| e == (p[a], p[b]) || e == (p[b], p[a]))
+        }))
+}
+
+/// Returns `true` if two squares are the same up to symmetry, see [Square::canonical].
+///
+/// Squares whose edges do not connect are only equivalent when they are equal.
+pub fn equivalent(a: &Square, b: &Square) -> bool {
+    match (a.canonical(), b.canonical()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+// Creates square `<left>[<top> -> <bottom>] <=> <right>`.
+fn square(left: Expr, top: Expr, bottom: Expr, right: Expr) -> Expr {
+    crate::iso_n(2, crate::path(left, crate::dir(top, bottom)), right)
+}
+
+/// Describes which squares of a pasting are known to be pullbacks.
+///
+/// The first square is pasted to the left of or above the second square,
+/// see [Square::paste_horizontal] and [Square::paste_vertical],
+/// such that the corner of the pullback is in the first square.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pullbacks {
+    /// Whether the first square is a pullback.
+    pub first: bool,
+    /// Whether the second square is a pullback.
+    pub second: bool,
+    /// Whether the outer square is a pullback.
+    pub outer: bool,
+}
+
+impl Pullbacks {
+    /// Adds the pullbacks that follow from the pasting lemma.
+    ///
+    /// When the second square is a pullback,
+    /// the first square is a pullback if and only if the outer square is.
+    pub fn paste(self) -> Pullbacks {
+        let both = self.second && (self.first || self.outer);
+        Pullbacks {first: self.first || both, second: self.second, outer: self.outer || both}
+    }
+}
+
+/// Composes morphisms of two edges following each other.
+///
+/// Returns `None` if an edge is unknown or the edges point in opposite directions.
+pub fn compose(a: Morphism, b: Morphism) -> Option<Morphism> {
+    // Iso and zero hold in both directions.
+    fn neutral(x: Morphism) -> bool {matches!(x, Iso | RevIso | Zero | RevZero)}
+    // Returns whether a morphism is mono, epi and right inverse.
+    fn props(x: Morphism) -> [bool; 3] {
+        match x {
+            Mono => [true, false, false],
+            Epi => [false, true, false],
+            EpiMono => [true, true, false],
+            RightInv => [false, true, true],
+            _ => [false, false, false],
+        }
+    }
+
+    if a == Unknown || b == Unknown {return None}
+    let rev = if neutral(a) {
+        is_reversed(b) && (is_reversed(a) || !neutral(b))
+    } else {
+        if !neutral(b) && is_reversed(a) != is_reversed(b) {return None}
+        is_reversed(a)
+    };
+    let fwd = |x| if is_reversed(x) {reverse(x)} else {x};
+    let res = match (fwd(a), fwd(b)) {
+        (Zero, _) | (_, Zero) => Zero,
+        (Iso, x) | (x, Iso) => x,
+        (a, b) => {
+            let (a, b) = (props(a), props(b));
+            match [a[0] && b[0], a[1] && b[1], a[2] && b[2]] {
+                [_, _, true] => RightInv,
+                [true, true, _] => EpiMono,
+                [true, _, _] => Mono,
+                [_, true, _] => Epi,
+                _ => Dir,
+            }
+        }
+    };
+    Some(if rev {reverse(res)} else {res})
+}
+
+impl Edges for Square {
+    fn bind(&self) -> &[Expr] {&self.bind}
+    fn labels(&self) -> &[[u8; 3]] {&self.labels}
+    fn code(&self) -> &[Morphism] {&self.code}
+    fn ends(&self, edge: usize) -> Option<[u8; 2]> {ends(&self.labels, edge)}
+    fn check(&self) -> Result<(), Error> {check_corners(0, &self.labels)}
+    type Target = Expr;
+    fn rewrite(&self, new_code: &[Morphism], e: &mut Expr) {
+        rewrite_square(new_code, e);
+        rewrite_objects(&self.bind, &self.labels, new_code, e);
+    }
+}
+
+// Rewrites square `<left>[<top> -> <bottom>] <=> <right>` using morphism codes.
+fn rewrite_square(new_code: &[Morphism], e: &mut Expr) {
+    use crate::Expr::*;
+
+    if let Mor(Iso, 2, a) = e {
+        let a = Arc::make_mut(a);
+        if let Some((mor, ab)) = mor_mut(&mut a.1) {
+            fix(mor, new_code[3], ab);
+        }
+        if let Path(ltb) = &mut a.0 {
+            rewrite_path(Arc::make_mut(ltb), new_code);
+        }
+    }
+}
+
+// Returns morphism and end-points of an edge, looking through names.
+fn mor_mut(e: &mut Expr) -> Option<(&mut Morphism, &mut Arc<(Expr, Expr)>)> {
+    match e {
+        Expr::Mor(mor, _, ab) => Some((mor, ab)),
+        Expr::Named(_, a) => mor_mut(Arc::make_mut(a)),
+        _ => None,
+    }
+}
+
+/// Returns the edges of a square or triangle, in the order of [EDGES].
+pub fn face_edges(e: &Expr) -> Vec<Expr> {
+    match e {
+        Expr::Mor(_, _, a) => {
+            let mut res = face_edges(&a.0);
+            res.push(a.1.clone());
+            res
+        }
+        _ => [e.left(), e.top(), e.bottom()].into_iter().flatten().collect(),
+    }
+}
+
+// Writes an edge in the opposite direction, keeping its name.
+fn flip(e: &Expr) -> Expr {
+    match e {
+        Expr::Mor(mor, n, ab) => Expr::Mor(reverse(*mor), *n, Arc::new((ab.1.clone(), ab.0.clone()))),
+        Expr::Comp(gf) => Expr::Comp(Arc::new((flip(&gf.1), flip(&gf.0)))),
+        Expr::Named(name, a) => Expr::Named(name.clone(), Arc::new(flip(a))),
+        _ => e.clone(),
+    }
+}
+
+// Sets morphism, swapping end-points when direction changes.
+fn fix(mor: &mut Morphism, code: Morphism, ab: &mut Arc<(Expr, Expr)>) {
+    if is_reversed(code) != is_reversed(*mor) {
+        *ab = Arc::new((ab.1.clone(), ab.0.clone()));
+    }
+    *mor = code;
+}
+
+// Rewrites path `<left>[<top> -> <bottom>]` using the first three morphism codes.
+fn rewrite_path(ltb: &mut (Expr, Expr), new_code: &[Morphism]) {
+    use crate::Expr::*;
+
+    if let Some((mor, _)) = mor_mut(&mut ltb.0) {
+        *mor = new_code[0];
+    }
+    if let Mor(_, _, tb) = &mut ltb.1 {
+        let tb = Arc::make_mut(tb);
+        if let Some((mor, ab)) = mor_mut(&mut tb.0) {
+            fix(mor, new_code[1], ab);
+        }
+        if let Some((mor, ab)) = mor_mut(&mut tb.1) {
+            fix(mor, new_code[2], ab);
+        }
+    }
+}
+
+/// Represents a commutative triangle.
+///
+/// A triangle `<left>[<top> -> <bottom>]` is a square
+/// where the right edge is the identity morphism of the shared target.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Triangle {
+    /// Bound expressions.
+    pub bind: Vec<Expr>,
+    /// Labels of edges, using the same format as [Square].
+    pub labels: [[u8; 3]; 3],
+    /// Morphism codes of the edges.
+    pub code: [Morphism; 3],
+    /// Names of the edges.
+    pub names: [Option<Arc<String>>; 3],
+}
+
+impl Triangle {
+    /// Creates a new triangle.
+    pub fn new(expr: &Expr) -> Result<Self, Error> {
+        use crate::Expr::*;
+
+        let mut bind: Vec<Expr> = vec![];
+        if let Path(ltb) = expr {
+            match &ltb.1 {
+                Mor(_, _, tb) => {
+                    let edges = [&ltb.0, &tb.0, &tb.1];
+                    let spans = [span(&ltb.0)?, span(&tb.0)?, span(&tb.1)?];
+                    let mut labels = spans.each_ref().map(|e| new(&mut bind, e));
+                    let code = std::array::from_fn(|i| edge_code(&spans[i], i, &mut labels));
+                    let names = edges.map(|e| e.name().cloned());
+                    Ok(Triangle {labels, bind, code, names})
+                }
+                _ => Err(Error::Shape(Shape::MissingArrow)),
+            }
+        } else {
+            Err(Error::Shape(Shape::MissingPath))
+        }
+    }
+
+    /// Evaluates triangle.
+    ///
+    /// Returns the original morphisms if the solver failed.
+    pub fn eval(&self) -> [Morphism; 3] {
+        self.try_eval().unwrap_or(self.code)
+    }
+
+    /// Evaluates triangle, reporting an error if the solver failed.
+    pub fn try_eval(&self) -> Result<[Morphism; 3], Error> {
+        let new_code = Solver::shared()?.eval(self)?;
+        Ok(std::array::from_fn(|i| new_code[i]))
+    }
+
+    /// Explains why the solver upgraded edges of the triangle.
+    pub fn explain(&self) -> Result<Vec<Explanation>, Error> {
+        Solver::shared()?.explain(self)
+    }
+
+    /// Update triangle.
+    pub fn update(&self, e: &mut Expr) -> Result<(), Error> {
+        Solver::shared()?.update(self, e)
+    }
+}
+
+impl Edges for Triangle {
+    fn bind(&self) -> &[Expr] {&self.bind}
+    fn labels(&self) -> &[[u8; 3]] {&self.labels}
+    fn code(&self) -> &[Morphism] {&self.code}
+    fn ends(&self, edge: usize) -> Option<[u8; 2]> {ends(&self.labels, edge)}
+    fn check(&self) -> Result<(), Error> {check_corners(0, &self.labels)}
+    type Target = Expr;
+    fn rewrite(&self, new_code: &[Morphism], e: &mut Expr) {
+        if let Expr::Path(ltb) = e {
+            rewrite_path(Arc::make_mut(ltb), new_code);
+        }
+        rewrite_objects(&self.bind, &self.labels, new_code, e);
+    }
+}
+
+/// Represents a commutative diagram made of several faces sharing edges.
+///
+/// Every face is a square or a triangle.
+/// Edges are shared between faces when they have the same end-points, morphism and name,
+/// possibly written in reverse direction, e.g. `A -> B` and `B <- A`.
+/// Objects and edges use the same format as [Square],
+/// such that the solver runs over all edges at once.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diagram {
+    /// Bound expressions.
+    pub bind: Vec<Expr>,
+    /// Labels of edges.
+    pub labels: Vec<[u8; 3]>,
+    /// Morphism codes of the edges.
+    pub code: Vec<Morphism>,
+    /// Names of the edges.
+    pub names: Vec<Option<Arc<String>>>,
+    /// Compositions of morphisms, see [Edges::comps].
+    pub comps: Vec<[u8; 3]>,
+    /// Faces of the diagram.
+    pub faces: Vec<Face>,
+}
+
+/// A commuting face of a diagram.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Face {
+    /// The edges of the face, in the same order as for [Square] or [Triangle].
+    ///
+    /// Stores the index of the edge in the diagram
+    /// and whether the face uses the edge in reverse direction.
+    pub edges: Vec<(usize, bool)>,
+    /// The morphisms of compositions in the face, e.g. `A -> B` and `B -> C` in `A -> B -> C`,
+    /// using the same format as edges.
+    pub parts: Vec<(usize, bool)>,
+}
+
+impl Diagram {
+    /// Creates a new diagram from a list of faces.
+    pub fn new(faces: &[Expr]) -> Result<Self, Error> {
+        let mut d = Diagram {
+            bind: vec![],
+            labels: vec![],
+            code: vec![],
+            names: vec![],
+            comps: vec![],
+            faces: vec![],
+        };
+        for expr in faces {
+            let (bind, labels, code, names) = match Square::new(expr) {
+                Ok(sq) => (sq.bind, sq.labels.to_vec(), sq.code.to_vec(), sq.names.to_vec()),
+                Err(Error::Shape(Shape::MissingIso)) if matches!(expr, Expr::Path(_)) => {
+                    let tr = Triangle::new(expr)?;
+                    (tr.bind, tr.labels.to_vec(), tr.code.to_vec(), tr.names.to_vec())
+                }
+                Err(err) => return Err(err),
+            };
+            let mut edges = vec![];
+            for ((label, code), name) in labels.into_iter().zip(code).zip(names) {
+                let mut g = |i: u8| if i == 0 {0} else {find(&mut d.bind, &bind[(i - 1) as usize])};
+                let label = [g(label[0]), g(label[1]), g(label[2])];
+                edges.push(d.insert(label, code, name));
+            }
+            let (mut exprs, mut comps) = (vec![], vec![]);
+            parts(expr, &mut exprs, &mut comps);
+            let parts = exprs.iter().map(|e| d.insert_forward(e)).collect();
+            d.add_comps(&comps);
+            d.faces.push(Face {edges, parts});
+        }
+        Ok(d)
+    }
+
+    /// Adds a morphism that is not part of any face, e.g. a hypothesis `A !-> B`.
+    ///
+    /// A composition, e.g. `A -> B -> C`, adds its morphisms
+    /// and an edge from its source to its target.
+    /// The solver uses the morphism as knowledge about the faces, and may upgrade it.
+    /// Returns the index of the edge and whether it is stored in reverse direction.
+    /// Returns [Error::Shape] if the morphisms of a composition do not meet.
+    pub fn assume(&mut self, e: &Expr) -> Result<(usize, bool), Error> {
+        let (mut exprs, mut comps) = (vec![], vec![]);
+        parts(e, &mut exprs, &mut comps);
+        for x in &exprs {self.insert_forward(x);}
+        self.add_comps(&comps);
+        let x = span(e)?;
+        Ok(match e.name() {
+            Some(name) => self.insert_forward(&Expr::Named(name.clone(), Arc::new(x))),
+            None => self.insert_forward(&x),
+        })
+    }
+
+    // Adds compositions `[<first>, <middle>, <last>]`, see [Edges::comps].
+    fn add_comps(&mut self, comps: &[[Expr; 3]]) {
+        for c in comps {
+            let c = c.each_ref().map(|x| find(&mut self.bind, x));
+            if !self.comps.contains(&c) {self.comps.push(c)}
+        }
+    }
+
+    // Finds morphism, adding it in forward direction such that the solver can upgrade it.
+    fn insert_forward(&mut self, e: &Expr) -> (usize, bool) {
+        let [obj, a, b] = new(&mut self.bind, e.unnamed());
+        let code = if let Expr::Mor(mor, _, _) = e.unnamed() {*mor} else {Unknown};
+        let rev = is_reversed(code);
+        let (label, code) = if rev {([obj, b, a], reverse(code))} else {([obj, a, b], code)};
+        let (i, swapped) = self.insert(label, code, e.name().cloned());
+        (i, swapped != rev)
+    }
+
+    // Finds edge, adding it if necessary.
+    fn insert(&mut self, label: [u8; 3], code: Morphism, name: Option<Arc<String>>) -> (usize, bool) {
+        let [obj, a, b] = label;
+        for (i, (&l, &c)) in self.labels.iter().zip(&self.code).enumerate() {
+            if self.names[i] != name {continue}
+            if l == label && c == code {return (i, false)}
+            if obj == 0 && l == [0, b, a] && c == reverse(code) {return (i, true)}
+        }
+        self.labels.push(label);
+        self.code.push(code);
+        self.names.push(name);
+        (self.labels.len() - 1, false)
+    }
+
+    /// Returns the labels of the edges of a face, in the direction of the face.
+    pub fn face_labels(&self, face: usize) -> Vec<[u8; 3]> {
+        self.faces[face].edges.iter()
+            .map(|&(i, rev)| {
+                let [obj, a, b] = self.labels[i];
+                if rev {[obj, b, a]} else {[obj, a, b]}
+            })
+            .collect()
+    }
+
+    /// Returns the edges of faces whose direction is corrected to meet the other edges.
+    ///
+    /// The faces are the ones the diagram was created from.
+    pub fn corrections(&self, faces: &[Expr]) -> Vec<Correction> {
+        let mut res = vec![];
+        for (i, face) in faces.iter().enumerate() {
+            let labels = self.face_labels(i);
+            for (j, e) in face_edges(face).into_iter().enumerate() {
+                let [0, a, _] = labels[j] else {continue};
+                if !matches!(e.unnamed(), Expr::Mor(_, _, _) | Expr::Comp(_)) {continue}
+                if *e.source() != self.bind[(a - 1) as usize] {
+                    res.push(Correction {face: i, edge: j, corrected: flip(&e), original: e});
+                }
+            }
+        }
+        res
+    }
+
+    /// Returns the morphism codes of a face, given morphism codes of the diagram.
+    pub fn face_code(&self, face: usize, code: &[Morphism]) -> Vec<Morphism> {
+        self.faces[face].edges.iter()
+            .map(|&(i, rev)| if rev {reverse(code[i])} else {code[i]})
+            .collect()
+    }
+
+    /// Evaluates diagram.
+    ///
+    /// Returns the original morphisms if the solver failed.
+    pub fn eval(&self) -> Vec<Morphism> {
+        self.try_eval().unwrap_or_else(|_| self.code.clone())
+    }
+
+    /// Evaluates diagram, reporting an error if the solver failed.
+    pub fn try_eval(&self) -> Result<Vec<Morphism>, Error> {
+        Solver::shared()?.eval(self)
+    }
+
+    /// Explains why the solver upgraded edges of the diagram.
+    pub fn explain(&self) -> Result<Vec<Explanation>, Error> {
+        Solver::shared()?.explain(self)
+    }
+
+    /// Update faces of diagram.
+    pub fn update(&self, faces: &mut [Expr]) -> Result<(), Error> {
+        Solver::shared()?.update(self, faces)
+    }
+}
+
+impl Edges for Diagram {
+    fn bind(&self) -> &[Expr] {&self.bind}
+    fn labels(&self) -> &[[u8; 3]] {&self.labels}
+    fn code(&self) -> &[Morphism] {&self.code}
+    fn comps(&self) -> &[[u8; 3]] {&self.comps}
+    fn ends(&self, edge: usize) -> Option<[u8; 2]> {
+        self.faces.iter().enumerate().find_map(|(i, face)| {
+            let j = face.edges.iter().position(|&(k, _)| k == edge)?;
+            ends(&self.face_labels(i), j)
+        })
+    }
+    fn check(&self) -> Result<(), Error> {
+        (0..self.faces.len()).try_for_each(|i| check_corners(i, &self.face_labels(i)))
+    }
+    fn face_edge(&self, edge: usize) -> Option<(usize, usize)> {
+        self.faces.iter().enumerate()
+            .find_map(|(i, face)| Some((i, face.edges.iter().position(|&(k, _)| k == edge)?)))
+    }
+    type Target = [Expr];
+    fn rewrite(&self, new_code: &[Morphism], faces: &mut [Expr]) {
+        for (i, e) in faces.iter_mut().enumerate() {
+            let code = self.face_code(i, new_code);
+            if code.len() == 4 {
+                rewrite_square(&code, e);
+            } else if let Expr::Path(ltb) = e {
+                rewrite_path(Arc::make_mut(ltb), &code);
+            }
+            rewrite_objects(&self.bind, &self.face_labels(i), &code, e);
+            let mut parts = self.faces[i].parts.iter();
+            parts_mut(e, &mut |mor, ab| if let Some(&(k, rev)) = parts.next() {
+                fix(mor, if rev {reverse(new_code[k])} else {new_code[k]}, ab);
+            });
+        }
+    }
+}
+
+/// Structured result of solving a diagram.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Solution {
+    /// The solved faces of the diagram.
+    pub faces: Vec<Expr>,
+    /// The edges of every face, in the order of faces.
+    pub edges: Vec<SolvedEdge>,
+    /// Relations derived by the solver that could not be reintegrated,
+    /// and edges that are objects whose morphism could not be inferred.
+    pub diagnostics: Vec<Error>,
+    /// Edges whose direction was corrected, in the order of faces.
+    pub corrections: Vec<Correction>,
+}
+
+impl Solution {
+    /// Displays the solved faces using Unicode arrows, see [Expr::unicode].
+    pub fn unicode(&self) -> String {
+        self.faces.iter().map(|e| e.unicode().to_string()).collect::<Vec<_>>().join("; ")
+    }
+}
+
+impl fmt::Display for Solution {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, face) in self.faces.iter().enumerate() {
+            if i > 0 {write!(w, "; ")?}
+            write!(w, "{}", face)?;
+        }
+        Ok(())
+    }
+}
+
+/// Describes how the solver changed an edge of a face.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SolvedEdge {
+    /// The index of the face.
+    pub face: usize,
+    /// The index of the edge in the face, see [EDGES].
+    pub edge: usize,
+    /// The source object, if the edge is a morphism.
+    pub source: Option<Expr>,
+    /// The target object, if the edge is a morphism.
+    pub target: Option<Expr>,
+    /// The edge expression, if the edge is not a morphism.
+    pub object: Option<Expr>,
+    /// The morphism before solving.
+    pub input: Morphism,
+    /// The morphism after solving.
+    pub output: Morphism,
+    /// The level of the morphism, e.g. 1 for `->` and 0 for an object.
+    pub n: usize,
+    /// Whether end-points were swapped by direction correction.
+    pub swapped: bool,
+}
+
+/// Describes an edge written in the wrong direction relative to the other edges of its face.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Correction {
+    /// The index of the face.
+    pub face: usize,
+    /// The index of the edge in the face, see [EDGES].
+    pub edge: usize,
+    /// The edge as written.
+    pub original: Expr,
+    /// The edge with end-points swapped and morphism reversed, e.g. `a <- c` for `c -> a`.
+    pub corrected: Expr,
+}
+
+impl fmt::Display for Correction {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(w, "Corrected `{}` edge in face {} from `{}` to `{}`",
+            EDGES[self.edge], self.face, self.original, self.corrected)
+    }
+}
+
+/// Solves commutative diagrams using a rule base.
+///
+/// Parsing the rule base is done once when creating the solver,
+/// such that it can be reused for solving many diagrams.
+#[derive(Clone, Debug)]
+pub struct Solver {
+    /// Rules and facts of the rule base.
+    pub rules: Vec<avalog::Expr<sym::Sym>>,
+    /// Whether to refuse correcting the direction of edges, see [Solver::check_strict].
+    pub strict: bool,
+}
+
+impl Solver {
+    /// Creates a new solver using the rules for Category Theory in "assets/cat.txt".
+    ///
+    /// The rules are embedded in the library,
+    /// so this does not depend on the file system or working directory.
+    pub fn new() -> Result<Solver, Error> {
+        Solver::from_rules(include_str!("../assets/cat.txt"), Path::new(""))
+    }
+
+    /// Creates a new solver from Avalog rules.
+    ///
+    /// Imports in the rules are resolved relative to the `parent` directory.
+    pub fn from_rules(src: &str, parent: &Path) -> Result<Solver, Error> {
+        let mut solver = Solver {rules: vec![], strict: false};
+        solver.extend(src, parent)?;
+        Ok(solver)
+    }
+
+    /// Creates a new solver from an Avalog rule file.
+    ///
+    /// Imports in the file are resolved relative to the directory of the file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Solver, Error> {
+        let mut solver = Solver {rules: vec![], strict: false};
+        solver.extend_file(path)?;
+        Ok(solver)
+    }
+
+    /// Adds Avalog rules to the rule base.
+    ///
+    /// Imports in the rules are resolved relative to the `parent` directory.
+    /// Returns an error if the rules mention an avatar not in [AVATARS], [COMPOSITION] or [CONTRADICTION].
+    pub fn extend(&mut self, src: &str, parent: &Path) -> Result<(), Error> {
+        let rules = avalog::parse_str(src, parent).map_err(Error::Solver)?;
+        validate(&rules)?;
+        self.rules.extend(rules);
+        Ok(())
+    }
+
+    /// Adds rules from an Avalog rule file to the rule base.
+    ///
+    /// Imports in the file are resolved relative to the directory of the file.
+    /// Returns an error if the rules mention an avatar not in [AVATARS], [COMPOSITION] or [CONTRADICTION].
+    pub fn extend_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let rules = avalog::parse(path).map_err(Error::Solver)?;
+        validate(&rules)?;
+        self.rules.extend(rules);
+        Ok(())
+    }
+
+    /// Returns a solver shared between threads, which is created on first use.
+    pub fn shared() -> Result<&'static Solver, Error> {
+        use std::sync::OnceLock;
+
+        static SOLVER: OnceLock<Result<Solver, Error>> = OnceLock::new();
+        SOLVER.get_or_init(Solver::new).as_ref().map_err(|err| err.clone())
+    }
+
+    /// Returns [Error::Correction] in strict mode if an edge of the faces needs correction.
+    ///
+    /// The faces are the ones the diagram was created from, see [Diagram::corrections].
+    pub fn check_strict(&self, d: &Diagram, faces: &[Expr]) -> Result<(), Error> {
+        if !self.strict {return Ok(())}
+        match d.corrections(faces).into_iter().next() {
+            Some(c) => Err(Error::Correction(c)),
+            None => Ok(()),
+        }
+    }
+
+    /// Evaluates the edges of a diagram.
+    ///
+    /// Relations that can not be represented by the morphism of an edge
+    /// are reported as [Error::Unsupported],
+    /// faces that can not commute as [Error::Contradiction]
+    /// and derived contradictions as [Error::Inconsistent].
+    pub fn eval<T: Edges>(&self, d: &T) -> Result<Vec<Morphism>, Error> {
+        d.check()?;
+        let knowledge = self.expand(d.bind(), d.labels(), d.code(), d.comps());
+        self.consistent(d, &knowledge)?;
+        let (mut new_code, mut upgrades, mut unsupported) =
+            integrate(d.bind(), d.labels(), d.code(), &knowledge);
+        infer(d, &knowledge, &mut new_code, &mut upgrades);
+        if unsupported.is_empty() {Ok(new_code)} else {Err(unsupported.swap_remove(0))}
+    }
+
+    /// Explains why the solver upgraded edges of a diagram.
+    pub fn explain<T: Edges>(&self, d: &T) -> Result<Vec<Explanation>, Error> {
+        d.check()?;
+        let knowledge = self.expand(d.bind(), d.labels(), d.code(), d.comps());
+        self.consistent(d, &knowledge)?;
+        let (mut new_code, mut upgrades, _) = integrate(d.bind(), d.labels(), d.code(), &knowledge);
+        infer(d, &knowledge, &mut new_code, &mut upgrades);
+        Ok(explain(d.code(), &new_code, &upgrades, &knowledge))
+    }
+
+    /// Update expression of a diagram.
+    pub fn update<T: Edges>(&self, d: &T, e: &mut T::Target) -> Result<(), Error> {
+        d.rewrite(&self.eval(d)?, e);
+        Ok(())
+    }
+
+    /// Solve a string.
+    ///
+    /// Accepts a square `<left>[<top> -> <bottom>] <=> <right>`,
+    /// a triangle `<left>[<top> -> <bottom>]`
+    /// or a diagram with faces separated by `;`.
+    /// Returns the solution as a string.
+    pub fn solve_str(&self, a: &str) -> Result<String, Error> {
+        let mut faces = Parser::shared()?.parse_faces(a)?;
+        if self.strict {self.check_strict(&Diagram::new(&faces)?, &faces)?}
+        // Morphisms of compositions are solved as edges of a diagram.
+        let composed = faces.iter().any(|e| {
+            let (mut res, mut comps) = (vec![], vec![]);
+            parts(e, &mut res, &mut comps);
+            !res.is_empty()
+        });
+        if faces.len() > 1 || composed {
+            self.update(&Diagram::new(&faces)?, &mut faces[..])?;
+            let faces: Vec<String> = faces.iter().map(|e| format!("{}", e)).collect();
+            return Ok(faces.join("; "));
+        }
+
+        let mut a = faces.swap_remove(0);
+        match Square::new(&a) {
+            Ok(sq) => self.update(&sq, &mut a)?,
+            Err(Error::Shape(Shape::MissingIso)) if matches!(a, Expr::Path(_)) =>
+                self.update(&Triangle::new(&a)?, &mut a)?,
+            Err(err) => return Err(err),
+        }
+        Ok(format!("{}", a))
+    }
+
+    /// Solve a string, returning a structured result.
+    ///
+    /// Accepts the same input as [Solver::solve_str].
+    /// Relations that can not be reintegrated are reported as diagnostics instead of failing,
+    /// as well as edges that are objects whose morphism could not be inferred.
+    pub fn solve(&self, a: &str) -> Result<Solution, Error> {
+        let faces = Parser::shared()?.parse_faces(a)?;
+        let d = Diagram::new(&faces)?;
+        self.solve_diagram(&d, faces)
+    }
+
+    /// Solves a diagram created from faces, returning a structured result.
+    ///
+    /// The diagram may contain edges that are not part of any face, see [Diagram::assume].
+    /// Returns [Error::Correction] in strict mode, see [Solver::check_strict].
+    pub fn solve_diagram(&self, d: &Diagram, mut faces: Vec<Expr>) -> Result<Solution, Error> {
+        d.check()?;
+        self.check_strict(d, &faces)?;
+        let corrections = d.corrections(&faces);
+        let knowledge = self.expand(&d.bind, &d.labels, &d.code, &d.comps);
+        self.consistent(d, &knowledge)?;
+        let (mut new_code, mut upgrades, mut diagnostics) = integrate(&d.bind, &d.labels, &d.code, &knowledge);
+        infer(d, &knowledge, &mut new_code, &mut upgrades);
+        for (&[obj, _, _], &code) in d.labels.iter().zip(&new_code) {
+            if obj != 0 && code == Unknown {
+                diagnostics.push(Error::Uninferred(d.bind[(obj - 1) as usize].clone()));
+            }
+        }
+
+        let mut edges = vec![];
+        for (i, face) in faces.iter().enumerate() {
+            let input = d.face_code(i, &d.code);
+            let output = d.face_code(i, &new_code);
+            let exprs = face_edges(face);
+            for (j, &(k, rev)) in d.faces[i].edges.iter().enumerate() {
+                let [obj, a, b] = d.labels[k];
+                let [a, b] = if rev {[b, a]} else {[a, b]};
+                let bound = |i: u8| d.bind[(i - 1) as usize].clone();
+                let (n, swapped) = match exprs[j].unnamed() {
+                    Expr::Mor(_, n, ab) => (*n, ab.0 != bound(a)),
+                    e @ Expr::Comp(_) => (1, *e.source() != bound(a)),
+                    _ => (0, false),
+                };
+                edges.push(SolvedEdge {
+                    face: i,
+                    edge: j,
+                    source: if obj == 0 {Some(bound(a))} else {None},
+                    target: if obj == 0 {Some(bound(b))} else {None},
+                    object: if obj == 0 {None} else {Some(bound(obj))},
+                    input: input[j],
+                    output: output[j],
+                    n,
+                    swapped,
+                });
             }
         }
+        d.rewrite(&new_code, &mut faces);
+        Ok(Solution {faces, edges, diagnostics, corrections})
+    }
 
-        use std::sync::Arc;
-        use avalog::{infer, parse_str, rel, ava, solve_with_accelerator, Accelerator, ParseData};
+    /// Asks whether a morphism between objects of a diagram follows from its faces.
+    ///
+    /// Runs the same solver as [Solver::eval] and looks for the relations describing the goal,
+    /// e.g. `(C, iso'(D))` for `C <-> D`, returning their derivations when all of them are found.
+    /// Returns [Error::Goal] if the goal is not a morphism between objects of the diagram.
+    /// Returns [Error::Correction] in strict mode, see [Solver::check_strict].
+    pub fn query(&self, faces: &[Expr], goal: &Expr) -> Result<Answer, Error> {
+        let d = Diagram::new(faces)?;
+        self.check_strict(&d, faces)?;
+        self.query_diagram(&d, goal)
+    }
 
-        let ref mut acc = Accelerator::new();
-        let ref parent = match std::env::current_dir() {
-            Ok(x) => x,
-            Err(_) => {
-                eprintln!("Could not get working directory");
-                return self.code;
+    /// Asks whether a morphism between objects of a diagram follows from its edges.
+    ///
+    /// See [Solver::query].
+    pub fn query_diagram(&self, d: &Diagram, goal: &Expr) -> Result<Answer, Error> {
+        d.check()?;
+        let (code, a, b) = match goal.unnamed() {
+            Expr::Mor(code, 1, ab) if *code != Unknown => {
+                let find = |x: &Expr| d.bind.iter().position(|y| y == x).map(|i| (i + 1) as u8);
+                match (find(&ab.0), find(&ab.1)) {
+                    (Some(a), Some(b)) => (*code, a, b),
+                    _ => return Err(Error::Goal(goal.clone())),
+                }
             }
+            _ => return Err(Error::Goal(goal.clone())),
         };
-        let mut start: ParseData<sym::Sym> = parse_str(include_str!("../assets/cat.txt"), parent).unwrap();
-
-        let zero: sym::Sym = Arc::new("zero".to_string()).into();
-        let iso: sym::Sym = Arc::new("iso".to_string()).into();
-        let right_inv: sym::Sym = Arc::new("right_inv".to_string()).into();
-        let mono: sym::Sym = Arc::new("mono".to_string()).into();
-        let epi: sym::Sym = Arc::new("epi".to_string()).into();
-        let mor: sym::Sym = Arc::new("mor".to_string()).into();
-
-        for i in 0..4 {
-            match self.labels[i] {
-                [0, a, b] => {
-                    let a: avalog::Expr<sym::Sym> = avalog::Expr::Sym(self.bind[(a - 1) as usize].clone().into());
-                    let b: avalog::Expr<sym::Sym> = avalog::Expr::Sym(self.bind[(b - 1) as usize].clone().into());
-                    let (a, b) = if is_reversed(self.code[i]) {(b, a)} else {(a, b)};
-                    match self.code[i] {
-                        Unknown => {}
-                        Dir | RevDir => start.push(rel(a, ava(mor.clone(), b))),
-                        Iso | RevIso => start.push(rel(a, ava(iso.clone(), b))),
-                        Mono | RevMono => start.push(rel(a, ava(mono.clone(), b))),
-                        Zero | RevZero => start.push(rel(a, ava(zero.clone(), b))),
-                        Epi | RevEpi => start.push(rel(a, ava(epi.clone(), b))),
-                        EpiMono | RevEpiMono => {
-                            start.push(rel(a.clone(), ava(epi.clone(), b.clone())));
-                            start.push(rel(a, ava(mono.clone(), b)));
-                        }
-                        RightInv | RevRightInv => start.push(rel(a, ava(right_inv.clone(), b))),
-                    }
+        let (a, b) = if is_reversed(code) {(b, a)} else {(a, b)};
+
+        let knowledge = self.expand(&d.bind, &d.labels, &d.code, &d.comps);
+        self.consistent(d, &knowledge)?;
+        let mut tracer = Tracer::new(&knowledge);
+        let derivations = avatars(code).iter().map(|&av| {
+            relations(&d.bind, &knowledge)
+                .find(|&(_, x, y, z)| (x, y.as_str(), z) == (a, av, b))
+                .map(|(fact, _, _, _)| tracer.derive(fact))
+        }).collect();
+        Ok(Answer {goal: goal.clone(), derivations})
+    }
+
+    /// Asks whether a morphism follows from a diagram, written `<diagram> |- <goal>`.
+    ///
+    /// For example, `(A <-> B)[(A <-> C) -> (B <-> D)] <=> (C -> D) |- C <-> D`.
+    /// See [Solver::query].
+    pub fn query_str(&self, a: &str) -> Result<Answer, Error> {
+        let (faces, goal) = Parser::shared()?.parse_query(a)?;
+        self.query(&faces, &goal)
+    }
+
+    // Returns an error if a contradiction was derived, see [CONTRADICTION].
+    //
+    // Leaves out edges one at a time while the contradiction still follows,
+    // such that every reported edge is needed.
+    fn consistent<T: Edges>(&self, d: &T, knowledge: &Knowledge) -> Result<(), Error> {
+        let Some(fact) = contradiction(knowledge) else {return Ok(())};
+        let (labels, code) = (d.labels(), d.code());
+        let mut edges: Vec<usize> = (0..labels.len()).filter(|&i| labels[i][0] == 0).collect();
+        for i in edges.clone() {
+            let rest: Vec<usize> = edges.iter().copied().filter(|&j| j != i).collect();
+            let labels: Vec<[u8; 3]> = rest.iter().map(|&j| labels[j]).collect();
+            let code: Vec<Morphism> = rest.iter().map(|&j| code[j]).collect();
+            if contradiction(&self.expand(d.bind(), &labels, &code, d.comps())).is_some() {edges = rest}
+        }
+        let edges = edges.into_iter().filter_map(|i| d.face_edge(i)).collect();
+        Err(Error::Inconsistent {fact, edges})
+    }
+
+    /// Expands knowledge about morphisms between bound expressions.
+    ///
+    /// The labels and morphism codes use the same format as [Square].
+    pub fn knowledge(&self, bind: &[Expr], labels: &[[u8; 3]], code: &[Morphism]) -> Knowledge {
+        self.expand(bind, labels, code, &[])
+    }
+
+    /// Expands knowledge about morphisms between bound expressions and their compositions.
+    ///
+    /// A composition `[<first>, <middle>, <last>]`, see [Edges::comps],
+    /// is added as facts `(K, first'(X))`, `(K, middle'(Y))` and `(K, last'(Z))`,
+    /// where `K` is the composition `X -> Y -> Z`.
+    pub fn expand(
+        &self,
+        bind: &[Expr],
+        labels: &[[u8; 3]],
+        code: &[Morphism],
+        comps: &[[u8; 3]]
+    ) -> Knowledge {
+        use avalog::{infer, rel, ava, solve_with_accelerator, Accelerator};
+
+        let acc = &mut Accelerator::new();
+        let mut start = self.rules.clone();
+        let axioms = start.len();
+
+        for (&label, &code) in labels.iter().zip(code) {
+            if let [0, a, b] = label {
+                let a: avalog::Expr<sym::Sym> = avalog::Expr::Sym(bind[(a - 1) as usize].clone().into());
+                let b: avalog::Expr<sym::Sym> = avalog::Expr::Sym(bind[(b - 1) as usize].clone().into());
+                let (a, b) = if is_reversed(code) {(b, a)} else {(a, b)};
+                for av in avatars(code) {
+                    let av: sym::Sym = Arc::new(av.to_string()).into();
+                    start.push(rel(a.clone(), ava(av, b.clone())));
                 }
-                _ => {}
             }
         }
+        for c in comps {
+            let [x, y, z] = c.map(|i| bind[(i - 1) as usize].clone());
+            let k = crate::comp(crate::dir(y.clone(), z.clone()), crate::dir(x.clone(), y.clone()));
+            let k: avalog::Expr<sym::Sym> = avalog::Expr::Sym(k.into());
+            for (av, x) in COMPOSITION.iter().zip([x, y, z]) {
+                let av: sym::Sym = Arc::new(av.to_string()).into();
+                start.push(rel(k.clone(), ava(av, avalog::Expr::Sym(x.into()))));
+            }
+        }
+        let premises = start.len();
 
-        let res = solve_with_accelerator(
+        let (facts, _) = solve_with_accelerator(
             &start,
             &[avalog::Expr::Ambiguity(false)],
             None,
@@ -283,87 +1536,153 @@ impl Square {
             infer,
             acc,
         );
+        Knowledge {facts, axioms, premises}
+    }
+}
 
-        let find = |name: &Expr| -> Option<u8> {
-            for (i, n) in self.bind.iter().enumerate() {
-                if n == name {return Some((i + 1) as u8)};
-            }
-            None
-        };
+// Checks that rules only mention avatars that can be reintegrated into morphisms.
+fn validate(rules: &[avalog::Expr<sym::Sym>]) -> Result<(), Error> {
+    fn unknown_avatar(e: &avalog::Expr<sym::Sym>) -> Option<Arc<String>> {
+        use avalog::Expr::*;
 
-        let mut new_code = self.code;
-        for x in &res.0 {
-            use avalog::Expr::*;
-
-            match x {
-                Rel(a, b) => {
-                    match (&**a, &**b) {
-                        (Sym(a), Ava(av, b)) => {
-                            if let sym::Sym::Expr(a) = a {
-                                if let Some(a_ind) = find(a) {
-                                    if let (Sym(av), Sym(b)) = (&**av, &**b) {
-                                        if let (sym::Sym::Ava(av), sym::Sym::Expr(b)) = (av, b) {
-                                            if let Some(b_ind) = find(b) {
-                                                for i in 0..4 {
-                                                    if self.labels[i][1] == a_ind &&
-                                                       self.labels[i][2] == b_ind {
-                                                           new_code[i] = update_code(new_code[i], av);
-                                                    }
-                                                    if self.labels[i][2] == b_ind &&
-                                                       self.labels[i][1] == a_ind {
-                                                           new_code[i] = update_code(new_code[i], av);
-                                                       }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-                _ => {}
+        match e {
+            Ava(av, b) => match &**av {
+                Sym(sym::Sym::Ava(av)) if !AVATARS.contains(&av.as_str()) &&
+                    !COMPOSITION.contains(&av.as_str()) && **av != CONTRADICTION => Some(av.clone()),
+                _ => unknown_avatar(b),
             }
+            Rel(a, b) => unknown_avatar(a).or_else(|| unknown_avatar(b)),
+            Rule(res, args) => unknown_avatar(res).or_else(|| args.iter().find_map(unknown_avatar)),
+            _ => None,
         }
+    }
 
-        new_code
+    match rules.iter().find_map(unknown_avatar) {
+        Some(av) => Err(Error::Avatar(av)),
+        None => Ok(()),
     }
+}
 
-    /// Update square.
-    pub fn update(&self, e: &mut Expr) {
-        fn fix(mor: &mut Morphism, code: Morphism, ab: &mut Arc<(Expr, Expr)>) {
-            if is_reversed(code) != is_reversed(*mor) {
-                *ab = Arc::new((ab.1.clone(), ab.0.clone()));
-            }
-            *mor = code;
-        }
+// Returns the first derived contradiction, see [CONTRADICTION].
+fn contradiction(knowledge: &Knowledge) -> Option<String> {
+    use avalog::Expr::*;
 
-        use crate::Expr::*;
-        use crate::Morphism::*;
-        use std::sync::Arc;
+    knowledge.facts.iter().find(|x| match x {
+        Rel(_, b) => matches!(&**b, Ava(av, _) if matches!(&**av, Sym(sym::Sym::Ava(av)) if **av == CONTRADICTION)),
+        _ => false,
+    }).map(|x| x.to_string())
+}
 
-        let new_code = self.eval();
-        if let Mor(Iso, 2, a) = e {
-            let a = Arc::make_mut(a);
-            if let Mor(mor, _, ab) = &mut a.1 {
-                fix(mor, new_code[3], ab);
-            }
-            if let Path(ltb) = &mut a.0 {
-                let ltb = Arc::make_mut(ltb);
-                if let Mor(mor, _, _) = &mut ltb.0 {
-                    *mor = new_code[0];
+// Returns relations `(X, av'(Y))` between bound expressions as `(fact, X, av, Y)`.
+fn relations<'a>(
+    bind: &'a [Expr],
+    knowledge: &'a Knowledge
+) -> impl Iterator<Item = (usize, u8, &'a Arc<String>, u8)> + 'a {
+    use avalog::Expr::*;
+
+    let find = |name: &Expr| -> Option<u8> {
+        for (i, n) in bind.iter().enumerate() {
+            if n == name {return Some((i + 1) as u8)};
+        }
+        None
+    };
+
+    knowledge.facts.iter().enumerate().filter_map(move |(fact, x)| {
+        if let Rel(a, b) = x {
+            if let (Sym(sym::Sym::Expr(a)), Ava(av, b)) = (&**a, &**b) {
+                if let (Sym(sym::Sym::Ava(av)), Sym(sym::Sym::Expr(b))) = (&**av, &**b) {
+                    return Some((fact, find(a)?, av, find(b)?));
                 }
-                if let Mor(_, _, tb) = &mut ltb.1 {
-                    let tb = Arc::make_mut(tb);
-                    if let Mor(mor, _, ab) = &mut tb.0 {
-                        fix(mor, new_code[1], ab);
-                    }
-                    if let Mor(mor, _, ab) = &mut tb.1 {
-                        fix(mor, new_code[2], ab);
+            }
+        }
+        None
+    })
+}
+
+// Analyzes new knowledge and reintegrates it into the edges.
+//
+// Relations that can not be reintegrated are skipped and returned as errors.
+fn integrate(
+    bind: &[Expr],
+    labels: &[[u8; 3]],
+    code: &[Morphism],
+    knowledge: &Knowledge
+) -> (Vec<Morphism>, Vec<Upgrade>, Vec<Error>) {
+    let mut new_code = code.to_vec();
+    let mut upgrades = vec![];
+    let mut unsupported = vec![];
+    for (fact, a_ind, av, b_ind) in relations(bind, knowledge) {
+        for (i, code) in new_code.iter_mut().enumerate() {
+            // Relations point in the direction of the morphism, see [avatars].
+            let [_, a, b] = labels[i];
+            let (a, b) = if is_reversed(*code) {(b, a)} else {(a, b)};
+            if a == a_ind && b == b_ind {
+                let from = *code;
+                match upgrade(from, av) {
+                    Ok(to) if to != from => {
+                        *code = to;
+                        upgrades.push(Upgrade {edge: i, from, to, fact});
                     }
+                    Ok(_) => {}
+                    Err(err) => unsupported.push(err),
+                }
+            }
+        }
+    }
+
+    (new_code, upgrades, unsupported)
+}
+
+// Infers morphisms of edges that are objects, using relations between their end-points.
+//
+// Prefers a morphism in the direction of the face over one in the opposite direction.
+fn infer<T: Edges>(
+    d: &T,
+    knowledge: &Knowledge,
+    new_code: &mut [Morphism],
+    upgrades: &mut Vec<Upgrade>
+) {
+    for (i, new_code) in new_code.iter_mut().enumerate() {
+        if d.labels()[i][0] == 0 || *new_code != Unknown {continue}
+        let Some([a, b]) = d.ends(i) else {continue};
+        for (x, y, rev) in [(a, b, false), (b, a, true)] {
+            let mut code = Unknown;
+            let mut steps = vec![];
+            for (fact, a_ind, av, b_ind) in relations(d.bind(), knowledge) {
+                if (a_ind, b_ind) != (x, y) || !AVATARS.contains(&av.as_str()) {continue}
+                let from = code;
+                if let Ok(to) = upgrade(if from == Unknown {Dir} else {from}, av) {
+                    if to == from {continue}
+                    code = to;
+                    let (from, to) = if rev {(reverse(from), reverse(to))} else {(from, to)};
+                    steps.push(Upgrade {edge: i, from, to, fact});
                 }
             }
+            if code != Unknown {
+                *new_code = if rev {reverse(code)} else {code};
+                upgrades.extend(steps);
+                break;
+            }
         }
     }
 }
+
+// Explains every edge that changed.
+fn explain(
+    code: &[Morphism],
+    new_code: &[Morphism],
+    upgrades: &[Upgrade],
+    knowledge: &Knowledge
+) -> Vec<Explanation> {
+    let mut tracer = Tracer::new(knowledge);
+    let mut res = vec![];
+    for (i, (&from, &to)) in code.iter().zip(new_code).enumerate() {
+        if from == to {continue}
+        let steps = upgrades.iter()
+            .filter(|up| up.edge == i)
+            .map(|up| Step {from: up.from, to: up.to, derivation: tracer.derive(up.fact)})
+            .collect();
+        res.push(Explanation {edge: i, from, to, steps});
+    }
+    res
+}
@@ -0,0 +1,127 @@
+//! Graphviz DOT export.
+//!
+//! Exports the edges of an input diagram,
+//! or the relations between objects derived by the solver.
+//!
+//! Relations are drawn with the following styles:
+//!
+//! - `mor`: plain arrow
+//! - `mono`: arrow with a bar at the tail
+//! - `epi`: arrow with a double head
+//! - `iso`: arrow with heads in both directions
+//! - `right_inv`: bold arrow with a double head
+//! - `zero`: dashed line
+//!
+//! Derived relations are blue, while relations from the input are black.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::code::{is_reversed, Diagram, Edges, Knowledge, Solver, AVATARS};
+use crate::parsing::Parser;
+use crate::sym;
+use crate::{Error, Expr, Morphism};
+use crate::Morphism::*;
+
+// Returns DOT attributes for a relation.
+fn style(rel: &str) -> &'static str {
+    match rel {
+        "mono" => "dir=both, arrowtail=tee",
+        "epi" => "arrowhead=normalnormal",
+        "epi_mono" => "dir=both, arrowtail=tee, arrowhead=normalnormal",
+        "iso" => "dir=both",
+        "right_inv" => "style=bold, arrowhead=normalnormal",
+        "zero" => "style=dashed, arrowhead=none",
+        _ => "",
+    }
+}
+
+// Returns the relation drawn for a morphism.
+fn relation(code: Morphism) -> &'static str {
+    match code {
+        Unknown | Dir | RevDir => "mor",
+        Mono | RevMono => "mono",
+        Epi | RevEpi => "epi",
+        EpiMono | RevEpiMono => "epi_mono",
+        RightInv | RevRightInv => "right_inv",
+        Iso | RevIso => "iso",
+        Zero | RevZero => "zero",
+    }
+}
+
+// Writes string as a quoted DOT identifier.
+fn id(w: &mut String, a: &str) {
+    w.push('"');
+    for c in a.chars() {
+        if c == '"' || c == '\\' {w.push('\\')}
+        w.push(c);
+    }
+    w.push('"');
+}
+
+fn edge(w: &mut String, a: &Expr, b: &Expr, rel: &str, derived: bool) {
+    w.push_str("    ");
+    id(w, &a.to_string());
+    w.push_str(" -> ");
+    id(w, &b.to_string());
+    let _ = write!(w, " [label=\"{}\"", rel);
+    let style = style(rel);
+    if !style.is_empty() {let _ = write!(w, ", {}", style);}
+    if derived {w.push_str(", color=blue, fontcolor=blue")}
+    w.push_str("];\n");
+}
+
+/// Exports the edges of a diagram.
+///
+/// Edges that are objects have no end-points and are left out.
+pub fn diagram<T: Edges>(d: &T) -> String {
+    let mut w = String::from("digraph {\n");
+    for (&[_, a, b], &code) in d.labels().iter().zip(d.code()) {
+        if a == 0 {continue}
+        let (a, b) = if is_reversed(code) {(b, a)} else {(a, b)};
+        let bind = d.bind();
+        edge(&mut w, &bind[(a - 1) as usize], &bind[(b - 1) as usize], relation(code), false);
+    }
+    w.push_str("}\n");
+    w
+}
+
+/// Exports every relation between objects known by the solver.
+///
+/// Identity relations from an object to itself and relations describing compositions are left out.
+pub fn knowledge(k: &Knowledge) -> String {
+    use avalog::Expr::*;
+
+    let mut w = String::from("digraph {\n");
+    let mut visited = HashSet::new();
+    for (i, x) in k.facts.iter().enumerate().skip(k.axioms) {
+        if let Rel(a, b) = x {
+            if let (Sym(sym::Sym::Expr(a)), Ava(av, b)) = (&**a, &**b) {
+                if let (Sym(sym::Sym::Ava(av)), Sym(sym::Sym::Expr(b))) = (&**av, &**b) {
+                    if a == b || !AVATARS.contains(&av.as_str()) || !visited.insert((a, av, b)) {continue}
+                    edge(&mut w, a, b, av, i >= k.premises);
+                }
+            }
+        }
+    }
+    w.push_str("}\n");
+    w
+}
+
+/// Parses a diagram and exports it.
+///
+/// Exports the relations known by the solver if `knowledge` is `true`,
+/// otherwise the edges of the diagram.
+pub fn export_str(solver: &Solver, a: &str, knowledge: bool) -> Result<String, Error> {
+    let d = Diagram::new(&Parser::shared()?.parse_faces(a)?)?;
+    Ok(export(solver, &d, knowledge))
+}
+
+/// Exports a diagram, see [export_str].
+pub fn export(solver: &Solver, d: &Diagram, knowledge: bool) -> String {
+    if knowledge {
+        self::knowledge(&solver.expand(&d.bind, &d.labels, &d.code, &d.comps))
+    } else {
+        diagram(d)
+    }
+}
@@ -0,0 +1,177 @@
+//! Error handling.
+
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::{Expr, Morphism};
+use crate::code::{Correction, EDGES};
+
+/// Describes why Caso failed to parse or solve an expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The input does not follow the syntax of Caso.
+    Syntax {
+        /// Byte range of the offending input.
+        range: Range<usize>,
+        /// Description of what the parser expected.
+        message: String,
+        /// Similar input that would be valid, e.g. a known arrow.
+        suggestion: Option<String>,
+    },
+    /// The input was parsed, but could not be converted into an expression.
+    Conversion {
+        /// Byte range of the input that could not be converted.
+        range: Range<usize>,
+    },
+    /// The expression does not have the shape of a commutative square.
+    Shape(Shape),
+    /// The syntax of Caso could not be compiled, which is an internal error.
+    Grammar(String),
+    /// The solver failed.
+    Solver(String),
+    /// A rule mentions an avatar that can not be reintegrated into morphisms.
+    Avatar(Arc<String>),
+    /// A relation derived by the solver can not be represented by the morphism of an edge.
+    Unsupported {
+        /// The morphism of the edge.
+        morphism: Morphism,
+        /// The avatar of the derived relation, e.g. `mono`.
+        avatar: Arc<String>,
+    },
+    /// The solver could not infer a morphism for an edge that is an object, e.g. `f`.
+    Uninferred(Expr),
+    /// A query is not a morphism between objects of the diagram.
+    Goal(Expr),
+    /// A file could not be read or written.
+    Io(String),
+    /// Edges of a face can not meet at the same objects in every corner,
+    /// in any direction, such that the face can not commute.
+    Contradiction {
+        /// The index of the face, 0 for a square or triangle.
+        face: usize,
+        /// The smallest set of conflicting edges, see [EDGES].
+        edges: Vec<usize>,
+    },
+    /// The solver derived a contradiction from the edges, see [crate::code::CONTRADICTION].
+    Inconsistent {
+        /// The derived contradiction, e.g. `(1, contradiction'(1))`.
+        fact: String,
+        /// A minimal set of conflicting edges as `(face, edge)`, see [EDGES],
+        /// such that the contradiction does not follow when leaving out any of them.
+        ///
+        /// Edges that are not part of any face are left out.
+        edges: Vec<(usize, usize)>,
+    },
+    /// The direction of an edge needs correction, which is refused in strict mode.
+    Correction(Correction),
+}
+
+/// Describes which part of a commutative square is missing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    /// The expression is not a 2-isomorphism `... <=> <right>`.
+    MissingIso,
+    /// The left side of `<=>` is not a path `<left>[...]`.
+    MissingPath,
+    /// The path does not contain a morphism `[<top> -> <bottom>]`.
+    MissingArrow,
+    /// The edges do not meet at four corners.
+    Disconnected,
+    /// The morphisms of a composition do not follow each other.
+    Composition,
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Shape::MissingIso =>
+                write!(w, "Expected `<left>[<top> -> <bottom>] <=> <right>` or `<left>[<top> -> <bottom>]`"),
+            Shape::MissingPath => write!(w, "Expected path `<left>[<top> -> <bottom>]` before `<=>`"),
+            Shape::MissingArrow => write!(w, "Expected morphism `[<top> -> <bottom>]` in path"),
+            Shape::Disconnected => write!(w, "Expected edges to meet at four corners"),
+            Shape::Composition =>
+                write!(w, "Expected morphisms of composition to follow each other in the same direction"),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Syntax {range, message, suggestion} => {
+                write!(w, "Syntax error at {}..{}: {}", range.start, range.end, message)?;
+                if let Some(x) = suggestion {write!(w, ", did you mean `{}`?", x)?}
+                Ok(())
+            }
+            Error::Conversion {range} =>
+                write!(w, "Could not convert {}..{} into expression", range.start, range.end),
+            Error::Shape(shape) => write!(w, "Could not convert into square: {}", shape),
+            Error::Grammar(msg) => write!(w, "Internal error: Could not compile syntax: {}", msg),
+            Error::Solver(msg) => write!(w, "Solver failed: {}", msg),
+            Error::Avatar(av) => write!(w, "Rules mention unknown avatar `{}`", av),
+            Error::Unsupported {morphism, avatar} =>
+                write!(w, "Can not upgrade `{:?}` using `{}`", morphism, avatar),
+            Error::Uninferred(e) => write!(w, "Could not infer morphism of edge `{}`", e),
+            Error::Goal(e) =>
+                write!(w, "Can not query `{}`: Expected a morphism between objects of the diagram", e),
+            Error::Io(msg) => write!(w, "File error: {}", msg),
+            Error::Contradiction {face, edges} => {
+                let edges: Vec<String> = edges.iter().map(|&i| format!("`{}`", EDGES[i])).collect();
+                write!(w, "Contradiction in face {}: Edges {} do not meet at the same corners in any direction",
+                    face, edges.join(", "))
+            }
+            Error::Inconsistent {fact, edges} => {
+                let edges: Vec<String> = edges.iter()
+                    .map(|&(face, i)| format!("`{}` in face {}", EDGES[i], face)).collect();
+                write!(w, "Contradiction `{}` follows from edges {}", fact, edges.join(", "))
+            }
+            Error::Correction(c) =>
+                write!(w, "Strict mode: Edge `{}` in face {} is written `{}`, expected `{}`",
+                    EDGES[c.edge], c.face, c.original, c.corrected),
+        }
+    }
+}
+
+impl Error {
+    /// Returns the byte range of the input causing the error, if known.
+    pub fn range(&self) -> Option<Range<usize>> {
+        match self {
+            Error::Syntax {range, ..} | Error::Conversion {range} => Some(range.clone()),
+            _ => None,
+        }
+    }
+
+    /// Moves the byte range of the error, when the input is part of a larger string.
+    pub fn offset(self, n: usize) -> Error {
+        match self {
+            Error::Syntax {range, message, suggestion} =>
+                Error::Syntax {range: range.start + n..range.end + n, message, suggestion},
+            Error::Conversion {range} => Error::Conversion {range: range.start + n..range.end + n},
+            err => err,
+        }
+    }
+
+    /// Renders error with the input and a caret line pointing at the offending characters.
+    ///
+    /// For example:
+    ///
+    /// ```text
+    /// A <--> B
+    ///   ^^^^
+    /// Syntax error at 2..6: Unknown arrow `<-->`, did you mean `<->`?
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        match self.range() {
+            Some(range) if range.start <= source.len() && source.is_char_boundary(range.start) => {
+                let end = range.end.clamp(range.start, source.len());
+                let col = source[..range.start].chars().count();
+                let width = source.get(range.start..end).map(|x| x.chars().count()).unwrap_or(0).max(1);
+                format!("{}\n{}{}\n{}", source, " ".repeat(col), "^".repeat(width), self)
+            }
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,101 @@
+//! JSON output of solver results.
+//!
+//! The format is written by hand to avoid extra dependencies:
+//!
+//! ```text
+//! {
+//!   "faces": ["<face>", ...],
+//!   "edges": [{
+//!     "face": 0, "edge": "left",
+//!     "source": "A", "target": "B", "object": null,
+//!     "input": "Dir", "output": "Iso", "n": 1, "swapped": false
+//!   }, ...],
+//!   "diagnostics": ["<message>", ...],
+//!   "corrections": [{
+//!     "face": 0, "edge": "top", "original": "c -> a", "corrected": "a <- c"
+//!   }, ...]
+//! }
+//! ```
+
+use std::fmt::Write;
+
+use crate::code::{Solution, SolvedEdge, EDGES};
+use crate::{Error, Expr};
+
+/// Writes string as JSON string literal.
+pub fn string(w: &mut String, a: &str) {
+    w.push('"');
+    for c in a.chars() {
+        match c {
+            '"' => w.push_str("\\\""),
+            '\\' => w.push_str("\\\\"),
+            '\n' => w.push_str("\\n"),
+            '\r' => w.push_str("\\r"),
+            '\t' => w.push_str("\\t"),
+            c if (c as u32) < 0x20 => {let _ = write!(w, "\\u{:04x}", c as u32);}
+            c => w.push(c),
+        }
+    }
+    w.push('"');
+}
+
+fn expr(w: &mut String, a: &Option<Expr>) {
+    match a {
+        Some(a) => string(w, &a.to_string()),
+        None => w.push_str("null"),
+    }
+}
+
+fn edge(w: &mut String, e: &SolvedEdge) {
+    let _ = write!(w, "{{\"face\":{},\"edge\":", e.face);
+    string(w, EDGES[e.edge]);
+    w.push_str(",\"source\":");
+    expr(w, &e.source);
+    w.push_str(",\"target\":");
+    expr(w, &e.target);
+    w.push_str(",\"object\":");
+    expr(w, &e.object);
+    let _ = write!(w, ",\"input\":\"{:?}\",\"output\":\"{:?}\",\"n\":{},\"swapped\":{}}}",
+        e.input, e.output, e.n, e.swapped);
+}
+
+/// Converts solution to JSON.
+pub fn solution(a: &Solution) -> String {
+    let mut w = String::new();
+    w.push_str("{\"faces\":[");
+    for (i, face) in a.faces.iter().enumerate() {
+        if i > 0 {w.push(',')}
+        string(&mut w, &face.to_string());
+    }
+    w.push_str("],\"edges\":[");
+    for (i, e) in a.edges.iter().enumerate() {
+        if i > 0 {w.push(',')}
+        edge(&mut w, e);
+    }
+    w.push_str("],\"diagnostics\":[");
+    for (i, err) in a.diagnostics.iter().enumerate() {
+        if i > 0 {w.push(',')}
+        string(&mut w, &err.to_string());
+    }
+    w.push_str("],\"corrections\":[");
+    for (i, c) in a.corrections.iter().enumerate() {
+        if i > 0 {w.push(',')}
+        let _ = write!(w, "{{\"face\":{},\"edge\":", c.face);
+        string(&mut w, EDGES[c.edge]);
+        w.push_str(",\"original\":");
+        string(&mut w, &c.original.to_string());
+        w.push_str(",\"corrected\":");
+        string(&mut w, &c.corrected.to_string());
+        w.push('}');
+    }
+    w.push_str("]}");
+    w
+}
+
+/// Converts error to JSON, e.g. `{"error":"..."}`.
+pub fn error(err: &Error) -> String {
+    let mut w = String::from("{\"error\":");
+    string(&mut w, &err.to_string());
+    w.push('}');
+    w
+}
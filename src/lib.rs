@@ -1,4 +1,6 @@
 #![deny(missing_docs)]
+#![allow(clippy::just_underscores_and_digits, clippy::toplevel_ref_arg)]
+#![allow(clippy::useless_conversion, clippy::from_over_into)]
 
 //! # Caso
 //!
@@ -20,6 +22,42 @@
 //!
 //! `caso`
 //!
+//! For scripts and CI, install the command line tool:
+//!
+//! `cargo install caso`
+//!
+//! Then, to solve expressions or check a catalogue of expected results:
+//!
+//! ```text
+//! caso solve "(A <-> B)[(A <-> C) -> (B -> C)]"
+//! caso check assets/examples.txt
+//! ```
+//!
+//! A catalogue contains inputs `> <expr>`, each followed by the expected output.
+//! The exit code is 1 if some expression fails or does not match, and 2 on usage errors.
+//!
+//! Use `caso solve --format json` to get one JSON object per expression,
+//! with the source, target and morphism of every edge before and after solving.
+//! The same result is available from `Solver::solve` in the library.
+//!
+//! In the REPL, type `view` to also draw solved squares as box diagrams:
+//!
+//! ```text
+//! A ──iso──→ C
+//! │          │
+//! iso        │
+//! ↓          ↓
+//! B ───────→ D
+//! ```
+//!
+//! To draw a solved square in LaTeX, use `caso tikz <expr>`, or `tikz <expr>` in the REPL.
+//! This prints a `tikzcd` environment, see the `tikz` module.
+//!
+//! To visualise a diagram with Graphviz, use `caso dot <expr>`, or `dot <expr>` in the REPL.
+//! Add `--knowledge` (`dot knowledge <expr>` in the REPL) to draw every relation derived by the solver.
+//!
+//! Enable the `serde` feature to serialize and deserialize `Expr`, `Morphism` and `Square`.
+//!
 //! ### Syntax
 //!
 //! A commuative diagram in Caso is written in the following grammar:
@@ -33,36 +71,176 @@
 //! the `c` is wrong relative to `a`,
 //! so, Caso corrects this to `(a -> b)[(a <- c) -> ...] <=> ...`.
 //!
+//! The corrected edges are listed in `Solution::corrections`,
+//! and highlighted in the REPL:
+//!
+//! ```text
+//! > (a -> b)[(c -> a) -> (b -> d)] <=> (c -> d)
+//! (a -> b)[(a <- c) -> (b -> d)] <=> (c -> d)
+//!          ^^^^^^^^
+//! NOTE: Corrected `top` edge in face 0 from `c -> a` to `a <- c`
+//! ```
+//!
+//! To refuse corrections and report an error instead, set `Solver::strict`,
+//! type `strict` in the REPL or use `--strict` in the command line tool.
+//!
+//! When the edges can not meet at the same corners in any direction,
+//! Caso reports a contradiction with the smallest set of conflicting edges,
+//! instead of a corrected expression:
+//!
+//! ```text
+//! > (A -> B)[(A -> C) -> (B -> D)] <=> (C -> E)
+//! ERROR:
+//! Contradiction in face 0: Edges `bottom`, `right` do not meet at the same corners in any direction
+//! ```
+//!
+//! Rules may also declare facts that can not hold together, by deriving `(X, contradiction'(Y))`.
+//! Caso then reports the contradiction with a minimal set of edges it follows from.
+//! For example, with a rule file stating that `1` is not a zero object,
+//! `(1, contradiction'(1)) :- (1, zero'(Y)).`, added using `rules add <file>`:
+//!
+//! ```text
+//! > (X <> Y)[(X -> 1) -> (Y -> D)] <=> (1 -> D)
+//! ERROR:
+//! Contradiction `(1, contradiction'(1))` follows from edges `left` in face 0, `bottom` in face 0, `right` in face 0
+//! ```
+//!
+//! The rules in "assets/cat.txt" derive no contradictions,
+//! since the syntax can not state that an object is not a zero object.
+//!
+//! When an expression can not be parsed, the error points at the offending characters,
+//! e.g. an unbalanced bracket or an unknown arrow, and suggests the nearest known arrow:
+//!
+//! ```text
+//! > (A <-/> B)
+//! ERROR:
+//! (A <-/> B)
+//!    ^^^^
+//! Syntax error at 3..7: Unknown arrow `<-/>`, did you mean `<->>`?
+//! ```
+//!
+//! Arrows written without whitespace to objects, e.g. `A<->B`, suggest adding it, e.g. `A <-> B`.
+//!
 //! Higher morpisms are supported by counting `-` (1) and `=` (2) in the arrow.
 //! For example, `<->` is a 1-isomorphism and `<=>` is a 2-isomorphism.
 //!
-//! | Morphism | Notation |
-//! | --- | --- |
-//! | Directional | `->` |
-//! | Reverse Directional | `<-` |
-//! | Epi | `->>` |
-//! | Reverse Epi | `<<-` |
-//! | Mono | `!->` |
-//! | Reverse Mono | `<-!` |
-//! | Right Inverse | `<->>` |
-//! | Left Inverse | `<<->` |
-//! | Epi-Mono | `!->>` |
-//! | Reverse Epi-Mono | `<<-!` |
-//! | Iso | `<->` |
-//! | Zero | `<>` |
+//! | Morphism | Notation | Unicode |
+//! | --- | --- | --- |
+//! | Directional | `->` | `→` |
+//! | Reverse Directional | `<-` | `←` |
+//! | Epi | `->>` | `↠` |
+//! | Reverse Epi | `<<-` | `↞` |
+//! | Mono | `!->` | `↣` |
+//! | Reverse Mono | `<-!` | `↢` |
+//! | Right Inverse | `<->>` | `⇄` |
+//! | Left Inverse | `<<->` | `⇆` |
+//! | Epi-Mono | `!->>` | `⤖` |
+//! | Reverse Epi-Mono | `<<-!` | `⬻` |
+//! | Iso | `<->` | `≅` or `↔` |
+//! | Zero | `<>` | `↮` |
+//!
+//! Unicode arrows are also available for some higher morphisms:
+//! `⇒` and `⇐` for `=>` and `<=`, `⇔` for `<=>`, `⇛` and `⇚` for `=->` and `<=-`.
+//! Other higher morphisms are written in ASCII.
+//! Unicode arrows do not need surrounding whitespace, e.g. `(A≅B)`.
+//! Use `Expr::unicode` to display an expression with Unicode arrows,
+//! `unicode` in the REPL or `--unicode` in the command line tool.
+//!
+//! Morphisms can be named, e.g. `f : A -> B`, to tell parallel arrows apart.
+//! The solver only reasons about the kind of morphism, but keeps names in the output:
+//!
+//! ```text
+//! > (f : A <-> B)[(g : A <-> C) -> (h : B <-> D)] <=> (k : D -> C)
+//! (f : A <-> B)[(g : A <-> C) -> (h : B <-> D)] <=> (k : C <-> D)
+//! ```
+//!
+//! An edge can be a composition, written as a chain `A -> B -> C` or as `g ∘ f`.
+//! The solver infers the morphisms of a composition using cancellation:
+//! if `g ∘ f` is mono then `f` is mono, and if `g ∘ f` is epi then `g` is epi.
+//!
+//! ```text
+//! > (A <-> B)[(A -> X -> C) -> (B !-> D)] <=> (C <-> D)
+//! (A <-> B)[(A !-> X -> C) -> (B !-> D)] <=> (C <-> D)
+//! ```
+//!
+//! An edge can be an unknown morphism, written as an object such as `f`.
+//! The solver infers the strongest morphism between the corners it connects,
+//! and names the inferred edge after the object:
+//!
+//! ```text
+//! > f[(A <-> C) -> (B <-> D)] <=> (C -> D)
+//! (f : A -> B)[(A <-> C) -> (B <-> D)] <=> (C -> D)
+//! ```
+//!
+//! When nothing can be inferred, the edge is kept and reported in `Solution::diagnostics`.
 //!
 //! ### How to solve triangles
 //!
-//! Triangles can be expanded into commutative square using identity morphisms.
+//! A commutative triangle is written `<left>[<top> -> <bottom>]`,
+//! where the left edge `A -> B` is followed by the bottom edge `B -> C`
+//! and equals the top edge `A -> C`.
 //!
 //! For example:
 //!
 //! ```text
-//! > (A <-> B)[(A -> C) -> (B -> C)] <=> (C -> C)
-//! (A <-> B)[(A -> C) -> (B -> C)] <=> (C <-> C)
+//! > (A <-> B)[(A <-> C) -> (B -> C)]
+//! (A <-> B)[(A <-> C) -> (B <-> C)]
 //! ```
 //!
-//! Here, `C -> C` is an identity morphism from `C` to itself.
+//! This gives the same result as padding the triangle into a square
+//! with the identity morphism `C -> C` as right edge.
+//!
+//! ### How to solve diagrams
+//!
+//! A diagram made of several squares and triangles is written as faces separated by `;`.
+//! Faces share an edge when it has the same end-points, morphism and name.
+//! The solver runs over all edges at once, such that knowledge from one face is used in another.
+//!
+//! For example:
+//!
+//! ```text
+//! > (A <-> B)[(A <-> C) -> (B -> C)]; (B -> C)[(B <-> D) -> (C -> D)]
+//! (A <-> B)[(A <-> C) -> (B <-> C)]; (B <-> C)[(B <-> D) -> (C <-> D)]
+//! ```
+//!
+//! ### How to query diagrams
+//!
+//! To ask whether a morphism follows from a diagram, write `<diagram> |- <goal>`,
+//! using `Solver::query_str` or `? <diagram> |- <goal>` in the REPL.
+//! The answer includes the derivation of the relations describing the goal:
+//!
+//! ```text
+//! > ? (A !-> B)[(A -> C) -> (B !-> D)] <=> (C -> D) |- A !-> D
+//! yes: A !-> D
+//!   (A, mono'(D)) by (X, mono'(Z)) :- (X, mono'(Y)), (Y, mono'(Z)).
+//!     (A, mono'(B)) [premise]
+//!     (B, mono'(D)) [premise]
+//! > ? (A -> B)[(A -> C) -> (B -> D)] <=> (C -> D) |- A <> D
+//! no: A <> D
+//! ```
+//!
+//! ### Sessions
+//!
+//! A `Session` keeps hypotheses across inputs, e.g. in the REPL:
+//!
+//! ```text
+//! > assume B ->> D
+//! 0: B ->> D
+//! > (A <-> B)[(A <-> C) -> (B -> D)] <=> (C -> D)
+//! (A <-> B)[(A <-> C) -> (B ->> D)] <=> (C ->> D)
+//! ```
+//!
+//! A hypothesis is an edge, e.g. `A !-> B`, `A => B` or `A -> B -> C`, or a face of a solved diagram.
+//! Use `hypotheses` to list them, `retract <n>` to remove one,
+//! and `save <file>` and `load <file>` to store them with one hypothesis per line.
+//!
+//! ### Equivalent squares
+//!
+//! The same square can be drawn in 8 ways by rotating and reflecting it,
+//! e.g. `(A -> B)[(A -> C) -> (B -> D)] <=> (C -> D)` transposed is `(A -> C)[(A -> B) -> (C -> D)] <=> (B -> D)`.
+//! `Square::canonical` picks one of these drawings, writing edges in the direction of the corners,
+//! and `code::equivalent` checks whether two squares are the same up to symmetry,
+//! e.g. to remove duplicates from a catalogue.
 //!
 //! ### Design
 //!
@@ -70,6 +248,10 @@
 //!
 //! The Avalog rules are located in "assets/cat.txt".
 //!
+//! Rules that hold in specific settings can be added to the solver,
+//! e.g. "assets/balanced.txt" for categories where epi-mono is iso.
+//! In the REPL, use `rules add <file>` or start with `--rules <file>`.
+//!
 //! The automated theorem prover uses the following steps:
 //!
 //! 1. Parse expression
@@ -81,15 +263,25 @@
 use std::sync::Arc;
 use std::fmt;
 
+pub use error::Error;
+
 use Expr::*;
 use Morphism::*;
 
 pub mod parsing;
 pub mod code;
 pub mod sym;
+pub mod proof;
+pub mod error;
+pub mod json;
+pub mod tikz;
+pub mod dot;
+pub mod render;
+pub mod session;
 
 /// Represents a morphism.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum Morphism {
     /// Unknown morphism.
@@ -124,8 +316,34 @@ pub enum Morphism {
     RevZero,
 }
 
+/// Unicode arrows for morphisms and their levels.
+///
+/// When several arrows represent the same morphism, the first one is used for output.
+/// Morphisms without an arrow in this table use ASCII notation.
+pub const UNICODE: [(&str, Morphism, usize); 18] = [
+    ("→", Dir, 1),
+    ("←", RevDir, 1),
+    ("↠", Epi, 1),
+    ("↞", RevEpi, 1),
+    ("↣", Mono, 1),
+    ("↢", RevMono, 1),
+    ("⇄", RightInv, 1),
+    ("⇆", RevRightInv, 1),
+    ("⤖", EpiMono, 1),
+    ("⬻", RevEpiMono, 1),
+    ("≅", Iso, 1),
+    ("↔", Iso, 1),
+    ("↮", Zero, 1),
+    ("⇒", Dir, 2),
+    ("⇐", RevDir, 2),
+    ("⇔", Iso, 2),
+    ("⇛", Dir, 3),
+    ("⇚", RevDir, 3),
+];
+
 /// Stores Caso expression.
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     /// A zero object.
     _0,
@@ -135,54 +353,162 @@ pub enum Expr {
     Mor(Morphism, usize, Arc<(Expr, Expr)>),
     /// A path.
     Path(Arc<(Expr, Expr)>),
+    /// A named morphism e.g. `f : A -> B`.
+    Named(Arc<String>, Arc<Expr>),
+    /// A composition `g ∘ f`, stored as `(g, f)`.
+    ///
+    /// Compositions of morphisms that meet are written as chains, e.g. `A -> B -> C`.
+    Comp(Arc<(Expr, Expr)>),
 }
 
 impl fmt::Display for Expr {
     fn fmt(&self, w: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        fn needs_parens(e: &Expr) -> bool {
-            match e {
-                Mor(_, _, _) => true,
-                _ => false,
-            }
-        }
+        self.write(w, false)
+    }
+}
+
+/// Displays expression using Unicode arrows, see [Expr::unicode].
+pub struct Unicode<'a>(pub &'a Expr);
+
+impl fmt::Display for Unicode<'_> {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        self.0.write(w, true)
+    }
+}
 
+impl Expr {
+    /// Displays expression using Unicode arrows where available, e.g. `A → B`.
+    pub fn unicode(&self) -> Unicode<'_> {Unicode(self)}
+
+    fn write(&self, w: &mut fmt::Formatter<'_>, unicode: bool) -> Result<(), std::fmt::Error> {
         match self {
             _0 => write!(w, "0")?,
             Obj(x) => write!(w, "{}", x)?,
-            Mor(mor, n, a) => {
-                if needs_parens(&a.0) {write!(w, "({}) ", a.0)?}
-                else {write!(w, "{} ", a.0)?}
-                write!(w, "{}", match mor {
-                    Zero | RevZero | Iso | RevIso | RevDir | RevMono | RightInv => "<",
-                    Mono | EpiMono => "!",
-                    RevEpi | RevEpiMono | RevRightInv => "<<",
-                    Unknown | Dir | Epi => "",
-                })?;
-                if mor != &Zero {
-                    for _ in 0..*n / 2 {write!(w, "=")?}
-                    for _ in 0..*n % 2 {write!(w, "-")?}
-                }
-                write!(w, "{}", match mor {
-                    Dir | Zero | RevZero | Iso | RevIso | Mono | RevRightInv => ">",
-                    Epi | RightInv | EpiMono => ">>",
-                    RevMono | RevEpiMono => "!",
-                    Unknown | RevDir | RevEpi => ""
-                })?;
-                if needs_parens(&a.1) {write!(w, " ({})", a.1)?}
-                else {write!(w, " {}", a.1)?}
+            Mor(_, _, a) => {
+                a.0.write_sub(w, unicode)?;
+                self.write_tail(w, unicode)?;
+            }
+            Comp(_) if self.is_chain() => {
+                self.source().write_sub(w, unicode)?;
+                self.write_tail(w, unicode)?;
+            }
+            Comp(a) => {
+                a.0.write_sub(w, unicode)?;
+                write!(w, " ∘ ")?;
+                a.1.write_sub(w, unicode)?;
             }
             Path(a) => {
-                let parens = needs_parens(&a.0);
-                if parens {write!(w, "({})", a.0)?}
-                else {write!(w, "{}", a.0)?};
-                write!(w, "[{}]", a.1)?;
+                a.0.write_sub(w, unicode)?;
+                write!(w, "[")?;
+                a.1.write(w, unicode)?;
+                write!(w, "]")?;
+            }
+            Named(name, a) => {
+                write!(w, "{} : ", name)?;
+                a.write(w, unicode)?;
             }
         }
         Ok(())
     }
+
+    // Writes sub-expression, with parentheses if needed.
+    fn write_sub(&self, w: &mut fmt::Formatter<'_>, unicode: bool) -> fmt::Result {
+        if matches!(self, Mor(_, _, _) | Named(_, _) | Comp(_)) {
+            write!(w, "(")?;
+            self.write(w, unicode)?;
+            write!(w, ")")
+        } else {
+            self.write(w, unicode)
+        }
+    }
+
+    // Writes arrows and targets of a morphism or a chain, leaving out the source.
+    fn write_tail(&self, w: &mut fmt::Formatter<'_>, unicode: bool) -> fmt::Result {
+        match self {
+            Mor(mor, n, a) => {
+                write!(w, " ")?;
+                // Reverse iso and reverse zero are written like iso and zero.
+                let m = match mor {RevIso => Iso, RevZero => Zero, x => *x};
+                let arrow = UNICODE.iter().find(|x| x.1 == m && (x.2 == *n || m == Zero));
+                match arrow {
+                    Some(x) if unicode => write!(w, "{}", x.0)?,
+                    _ => {
+                        write!(w, "{}", match mor {
+                            Zero | RevZero | Iso | RevIso | RevDir | RevMono | RightInv => "<",
+                            Mono | EpiMono => "!",
+                            RevEpi | RevEpiMono | RevRightInv => "<<",
+                            Unknown | Dir | Epi => "",
+                        })?;
+                        if m != Zero {
+                            for _ in 0..*n / 2 {write!(w, "=")?}
+                            for _ in 0..*n % 2 {write!(w, "-")?}
+                        }
+                        write!(w, "{}", match mor {
+                            Dir | Zero | RevZero | Iso | RevIso | Mono | RevRightInv => ">",
+                            Epi | RightInv | EpiMono => ">>",
+                            RevMono | RevEpiMono => "!",
+                            Unknown | RevDir | RevEpi => ""
+                        })?;
+                    }
+                }
+                write!(w, " ")?;
+                a.1.write_sub(w, unicode)
+            }
+            Comp(a) => {
+                a.1.write_tail(w, unicode)?;
+                a.0.write_tail(w, unicode)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Returns `true` if composition can be written as a chain, e.g. `A -> B -> C`.
+    fn is_chain(&self) -> bool {
+        match self {
+            Mor(_, _, _) => true,
+            Comp(a) => a.0.is_chain() && a.1.is_chain() && a.1.target() == a.0.source(),
+            _ => false,
+        }
+    }
+
+    /// Returns source of a morphism or composition, as written.
+    pub fn source(&self) -> &Expr {
+        match self {
+            Mor(_, _, a) => &a.0,
+            Comp(a) => a.1.source(),
+            Named(_, a) => a.source(),
+            _ => self,
+        }
+    }
+
+    /// Returns target of a morphism or composition, as written.
+    pub fn target(&self) -> &Expr {
+        match self {
+            Mor(_, _, a) => &a.1,
+            Comp(a) => a.0.target(),
+            Named(_, a) => a.target(),
+            _ => self,
+        }
+    }
 }
 
 impl Expr {
+    /// Returns name of morphism, if any.
+    pub fn name(&self) -> Option<&Arc<String>> {
+        match self {
+            Named(name, _) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Returns expression without name, e.g. `A -> B` for `f : A -> B`.
+    pub fn unnamed(&self) -> &Expr {
+        match self {
+            Named(_, a) => a.unnamed(),
+            _ => self,
+        }
+    }
+
     /// Returns left edge, if any.
     pub fn left(&self) -> Option<Expr> {
         match self {
@@ -215,12 +541,9 @@ impl Expr {
 }
 
 impl<'a> TryFrom<&'a str> for Expr {
-    type Error = String;
-    fn try_from(val: &'a str) -> Result<Expr, String> {
-        match parsing::parse_str(val) {
-            Ok(x) => Ok(x),
-            Err(err) => Err(format!("ERROR:\n{}", err)),
-        }
+    type Error = Error;
+    fn try_from(val: &'a str) -> Result<Expr, Error> {
+        parsing::parse_str(val)
     }
 }
 
@@ -304,6 +627,16 @@ pub fn rev_right_inv_n(n: usize, a: Expr, b: Expr) -> Expr {
 /// Reverse right inverse e.g. `A <<-> B`.
 pub fn rev_right_inv(a: Expr, b: Expr) -> Expr {rev_right_inv_n(1, a, b)}
 
+/// A named morphism e.g. `f : A -> B`.
+pub fn named(name: &str, a: Expr) -> Expr {
+    Named(Arc::new(name.into()), Arc::new(a))
+}
+
+/// A composition e.g. `g ∘ f`.
+pub fn comp(g: Expr, f: Expr) -> Expr {
+    Comp(Arc::new((g, f)))
+}
+
 /// A path e.g. `X[Y]`.
 pub fn path(a: Expr, b: Expr) -> Expr {
     Path(Arc::new((a, b)))
@@ -312,12 +645,9 @@ pub fn path(a: Expr, b: Expr) -> Expr {
 /// Solve a string.
 ///
 /// Returns the solution as a string.
-/// Produces an error message if the solver failed.
-pub fn solve_str(a: &str) -> Result<String, String> {
-    let mut a: Expr = a.try_into()?;
-    let sq = code::Square::new(&a).ok_or("Could not convert into square".to_string())?;
-    sq.update(&mut a);
-    Ok(format!("{}", a))
+/// Produces an error if the solver failed.
+pub fn solve_str(a: &str) -> Result<String, Error> {
+    code::Solver::shared()?.solve_str(a)
 }
 
 /// Converts string into expression (panics when format is invalid).
@@ -406,6 +736,7 @@ mod tests {
             bind: vec![conv("a"), conv("b"), conv("c"), conv("d")],
             labels: [[0, 1, 2], [0, 1, 3], [0, 2, 4], [0, 3, 4]],
             code: [Dir, RevDir, Dir, Iso],
+            names: Default::default(),
         });
         assert_eq!(code::eval(sq.code), [Iso; 4]);
 
@@ -413,6 +744,48 @@ mod tests {
         assert_eq!(Square::new(&a).unwrap().code, [Dir, Dir, RevDir, Dir]);
     }
 
+    #[test]
+    fn paste() {
+        use code::{compose, Square};
+
+        let a = Square::new(&conv("(a -> b)[(a -> c) -> (b -> d)] <=> (c -> d)")).unwrap();
+        let b = Square::new(&conv("(c -> d)[(c ->> e) -> (d <-> f)] <=> (e -> f)")).unwrap();
+        assert_eq!(a.corners().unwrap(), [&conv("a"), &conv("b"), &conv("c"), &conv("d")]);
+        let ab = a.paste_horizontal(&b).unwrap();
+        assert_eq!(ab, conv("(a -> b)[(a -> e) -> (b -> f)] <=> (e -> f)"));
+        assert_eq!(format!("{}", ab), "(a -> b)[(a -> e) -> (b -> f)] <=> (e -> f)");
+        assert_eq!(b.paste_horizontal(&a), None);
+
+        let c = Square::new(&conv("(b !-> x)[(b -> d) -> (x -> y)] <=> (d !-> y)")).unwrap();
+        let ac = a.paste_vertical(&c).unwrap();
+        assert_eq!(ac, conv("(a -> x)[(a -> c) -> (x -> y)] <=> (c -> y)"));
+        assert_eq!(a.paste_vertical(&b), None);
+
+        // Directions are corrected before pasting.
+        let d = Square::new(&conv("(c -> d)[(e <- c) -> (f <- d)] <=> (e -> f)")).unwrap();
+        let ad = a.paste_horizontal(&d).unwrap();
+        assert_eq!(ad, conv("(a -> b)[(a -> e) -> (b -> f)] <=> (e -> f)"));
+
+        // Pasting lemma for pullbacks.
+        use code::Pullbacks;
+        let p = |first, second, outer| Pullbacks {first, second, outer};
+        assert_eq!(p(true, true, false).paste(), p(true, true, true));
+        assert_eq!(p(false, true, true).paste(), p(true, true, true));
+        assert_eq!(p(true, false, false).paste(), p(true, false, false));
+        assert_eq!(p(false, false, true).paste(), p(false, false, true));
+        assert_eq!(p(false, true, false).paste(), p(false, true, false));
+
+        assert_eq!(compose(Mono, Mono), Some(Mono));
+        assert_eq!(compose(Mono, EpiMono), Some(Mono));
+        assert_eq!(compose(RightInv, EpiMono), Some(Epi));
+        assert_eq!(compose(Iso, RevEpi), Some(RevEpi));
+        assert_eq!(compose(RevIso, Iso), Some(Iso));
+        assert_eq!(compose(RevIso, RevIso), Some(RevIso));
+        assert_eq!(compose(Dir, Zero), Some(Zero));
+        assert_eq!(compose(Dir, RevDir), None);
+        assert_eq!(compose(Unknown, Iso), None);
+    }
+
     #[test]
     fn test_eval() {
         let a: Expr = conv("f[(X !-> 1) -> (0 !-> Y)] <=> (0 <-> 1)");
@@ -460,6 +833,329 @@ mod tests {
         assert_eq!(sq.eval(), [Iso, RevIso, Iso, Iso]);
     }
 
+    #[test]
+    fn explain() {
+        use proof::Derivation;
+
+        fn premises(d: &Derivation, res: &mut Vec<String>) {
+            match d {
+                Derivation::Premise(x) => res.push(format!("{}", x)),
+                Derivation::Rule {premises: ps, ..} => for p in ps {premises(p, res)},
+                Derivation::Axiom(_) => {}
+                Derivation::Unexplained(x) => panic!("Unexplained `{}`", x),
+            }
+        }
+
+        let a: Expr = conv("(A <-> B)[(A <-> C) -> (B <-> D)] <=> (C -> D)");
+        let sq = code::Square::new(&a).unwrap();
+        let ex = sq.explain().unwrap();
+        assert_eq!(ex.len(), 1);
+        assert_eq!((ex[0].edge, ex[0].from, ex[0].to), (3, Dir, Iso));
+        let last = ex[0].steps.last().unwrap();
+        assert_eq!(last.to, Iso);
+        assert_eq!(format!("{}", last.derivation.fact()), "(C, right_inv'(D))");
+        let mut res = vec![];
+        premises(&last.derivation, &mut res);
+        assert!(res.iter().all(|x| ["(A, iso'(B))", "(A, iso'(C))", "(B, iso'(D))"].contains(&&**x)));
+
+        // Facts explaining a reversed edge point in the direction of its morphism.
+        let a: Expr = conv("(A <-! B)[(A !-> C) -> (B <-! D)] <=> (C <- D)");
+        let sq = code::Square::new(&a).unwrap();
+        let ex = sq.explain().unwrap();
+        assert_eq!(ex.len(), 1);
+        assert_eq!((ex[0].edge, ex[0].from, ex[0].to), (3, RevDir, RevMono));
+        assert_eq!(format!("{}", ex[0].steps[0].derivation.fact()), "(D, mono'(C))");
+
+        let a: Expr = conv("(A -> B)[(C -> A) -> (B -> D)] <=> (D -> C)");
+        let sq = code::Square::new(&a).unwrap();
+        assert_eq!(sq.explain().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn errors() {
+        use error::Shape;
+
+        assert_eq!(solve_str("(A -> B"), Err(Error::Syntax {
+            range: 0..1,
+            message: "Unclosed `(`".into(),
+            suggestion: None,
+        }));
+        assert_eq!(solve_str("A <-> "), Err(Error::Syntax {
+            range: 6..6,
+            message: "Expected something".into(),
+            suggestion: None,
+        }));
+        assert_eq!(solve_str("(A -> B)[(A -> C) -> (B -> D)]] <=> (C -> D)"), Err(Error::Syntax {
+            range: 30..31,
+            message: "Unexpected `]`".into(),
+            suggestion: None,
+        }));
+        let a = "(A <->>> B)[(A -> C) -> (B -> D)] <=> (C -> D)";
+        let err = solve_str(a).unwrap_err();
+        assert_eq!(err, Error::Syntax {
+            range: 3..8,
+            message: "Unknown arrow `<->>>`".into(),
+            suggestion: Some("<->>".into()),
+        });
+        assert_eq!(err.render(a), "\
+            (A <->>> B)[(A -> C) -> (B -> D)] <=> (C -> D)\n   \
+            ^^^^^\n\
+            Syntax error at 3..8: Unknown arrow `<->>>`, did you mean `<->>`?");
+        // Arrows with several `-` are higher morphisms.
+        assert_eq!(parsing::parse_str("A <-->> B"), Ok(right_inv_n(2, conv("A"), conv("B"))));
+        assert_eq!(parsing::parse_str("A ->>> B").unwrap_err().to_string(),
+            "Syntax error at 2..6: Unknown arrow `->>>`, did you mean `->>`?");
+        // Internal errors do not point at the input.
+        let err = Error::Grammar("x".into());
+        assert_eq!(err.range(), None);
+        assert_eq!(err.render("A -> B"), "Internal error: Could not compile syntax: x");
+        // Known arrows need whitespace to object names.
+        assert_eq!(solve_str("(A<->B)[(A -> C) -> (B -> D)] <=> (C -> D)"), Err(Error::Syntax {
+            range: 2..5,
+            message: "Expected whitespace around arrow `<->`".into(),
+            suggestion: Some("A <-> B".into()),
+        }));
+        assert_eq!(parsing::parse_str("A<->>>B").unwrap_err().to_string(),
+            "Syntax error at 1..6: Unknown arrow `<->>>`, did you mean `<->>`?");
+        assert_eq!(parsing::parse_str("(A -> B]").unwrap_err().range(), Some(7..8));
+        assert!(parsing::arrows().contains(&"<=>".to_string()));
+        assert_eq!(solve_str("A -> B"), Err(Error::Shape(Shape::MissingIso)));
+        assert_eq!(solve_str("X <=> Y"), Err(Error::Shape(Shape::MissingPath)));
+        assert_eq!(solve_str("(A -> B)[C] <=> D"), Err(Error::Shape(Shape::MissingArrow)));
+    }
+
+    #[test]
+    fn upgrade() {
+        use code::{upgrade, AVATARS};
+
+        let all = [
+            Unknown, Dir, RevDir, Mono, RevMono, Epi, RevEpi, EpiMono, RevEpiMono,
+            RightInv, RevRightInv, Iso, RevIso, Zero, RevZero,
+        ];
+        for &a in &all {
+            for av in AVATARS {
+                let b = upgrade(a, av).unwrap();
+                // Upgrading never changes direction.
+                assert_eq!(code::is_reversed(a), code::is_reversed(b), "{:?} {}", a, av);
+            }
+            assert_eq!(upgrade(a, "foo"), Err(Error::Unsupported {
+                morphism: a,
+                avatar: Arc::new("foo".into()),
+            }));
+        }
+
+        assert_eq!(upgrade(Dir, "iso"), Ok(Iso));
+        assert_eq!(upgrade(Mono, "iso"), Ok(Iso));
+        assert_eq!(upgrade(RevMono, "right_inv"), Ok(RevIso));
+        assert_eq!(upgrade(Epi, "zero"), Ok(Zero));
+        assert_eq!(upgrade(Zero, "mono"), Ok(Zero));
+        assert_eq!(upgrade(Unknown, "mor"), Ok(Unknown));
+        assert_eq!(upgrade(Mono, "right_inv"), Ok(Iso));
+        assert_eq!(upgrade(RightInv, "mono"), Ok(Iso));
+        assert_eq!(upgrade(EpiMono, "right_inv"), Ok(Iso));
+        assert_eq!(upgrade(Dir, "left_inv"), Ok(Mono));
+        assert_eq!(upgrade(RevDir, "left_inv"), Ok(RevMono));
+
+        // Upgrading is a join: the order of relations does not matter.
+        let up = |a, av| upgrade(a, av).unwrap();
+        for &a in &all {
+            for x in AVATARS {
+                assert_eq!(up(up(a, x), x), up(a, x), "{:?} {}", a, x);
+                for y in AVATARS {
+                    assert_eq!(up(up(a, x), y), up(up(a, y), x), "{:?} {} {}", a, x, y);
+                }
+            }
+        }
+
+        // Reversed edges are only upgraded by relations in the direction of the morphism.
+        let solver = code::Solver::shared().unwrap();
+        let a = "(A !-> B)[(A <-! C) -> (B !-> D)] <=> (C <- D)";
+        assert_eq!(solve_str(a).unwrap(), "(A !-> B)[(A <-! C) -> (B !-> D)] <=> (C <- D)");
+        assert!(!solver.query_str(&format!("{} |- C <-! D", a)).unwrap().holds());
+        let a = "(A <-! B)[(A !-> C) -> (B <-! D)] <=> (C <- D)";
+        assert_eq!(solve_str(a).unwrap(), "(A <-! B)[(A !-> C) -> (B <-! D)] <=> (C <-! D)");
+        assert!(solver.query_str(&format!("{} |- C <-! D", a)).unwrap().holds());
+    }
+
+    #[test]
+    fn triangle() {
+        use code::Triangle;
+        use error::Shape;
+
+        let a = &solve_str("(A <-> B)[(A <-> C) -> (B -> C)]").unwrap();
+        assert_eq!(a, "(A <-> B)[(A <-> C) -> (B <-> C)]");
+        // Same result as padding with an identity morphism.
+        let b = &solve_str("(A <-> B)[(A <-> C) -> (B -> C)] <=> (C -> C)").unwrap();
+        assert_eq!(b, "(A <-> B)[(A <-> C) -> (B <-> C)] <=> (C <-> C)");
+
+        // Directions are corrected like for squares.
+        let a = &solve_str("(A -> B)[(C <- A) -> (C <- B)]").unwrap();
+        assert_eq!(a, "(A -> B)[(A -> C) -> (B -> C)]");
+        let a = &solve_str("(A -> B)[(A -> C) -> (C -> B)]").unwrap();
+        assert_eq!(a, "(A -> B)[(A -> C) -> (B <- C)]");
+
+        let t = Triangle::new(&conv("(A -> B)[(A -> C) -> (C -> B)]")).unwrap();
+        assert_eq!(t.code, [Dir, Dir, RevDir]);
+        assert_eq!(Triangle::new(&conv("A -> B")), Err(Error::Shape(Shape::MissingPath)));
+    }
+
+    #[test]
+    fn diagram() {
+        use code::Diagram;
+
+        let a = &solve_str("(A <-> B)[(A <-> C) -> (B -> C)]; (B -> C)[(B <-> D) -> (C -> D)]").unwrap();
+        assert_eq!(a, "(A <-> B)[(A <-> C) -> (B <-> C)]; (B <-> C)[(B <-> D) -> (C <-> D)]");
+        // Shared edge written in reverse direction.
+        let a = &solve_str("(A <-> B)[(A <-> C) -> (B -> C)]; (C <- B)[(B <-> D) -> (C -> D)]").unwrap();
+        assert_eq!(a, "(A <-> B)[(A <-> C) -> (B <-> C)]; (C <-> B)[(B <-> D) -> (C <-> D)]");
+        // A single face gives the same result as a square.
+        let x = "(A <-> B)[(A -> C) -> (B -> D)] <=> (C -> D)";
+        let faces = parsing::Parser::shared().unwrap().parse_faces(x).unwrap();
+        let d = Diagram::new(&faces).unwrap();
+        assert_eq!(d.eval(), code::Square::new(&faces[0]).unwrap().eval());
+
+        let faces = parsing::Parser::shared().unwrap()
+            .parse_faces("(A -> B)[(A -> C) -> (B -> D)] <=> (C -> D); (C -> D)[(C -> E) -> (D -> F)] <=> (E -> F)")
+            .unwrap();
+        let d = Diagram::new(&faces).unwrap();
+        assert_eq!(d.code.len(), 7);
+        assert_eq!(d.faces[0].edges[3], d.faces[1].edges[0]);
+        assert!(parsing::parse_str("(A -> B)[C -> D]; (B -> C)[D -> E]").is_err());
+    }
+
+    #[test]
+    fn solution() {
+        use code::{Solver, SolvedEdge};
+
+        let solver = Solver::shared().unwrap();
+        let a = solver.solve("(A <-> B)[(A <-> C) -> (C <- B)]").unwrap();
+        assert_eq!(a.to_string(), "(A <-> B)[(A <-> C) -> (B <-> C)]");
+        assert_eq!(a.edges.len(), 3);
+        assert_eq!(a.edges[2], SolvedEdge {
+            face: 0,
+            edge: 2,
+            source: Some(conv("B")),
+            target: Some(conv("C")),
+            object: None,
+            input: Dir,
+            output: Iso,
+            n: 1,
+            swapped: true,
+        });
+        assert!(a.diagnostics.is_empty());
+
+        let b = solver.solve("f[(X !-> 1) -> (0 !-> Y)] <=> (0 <-> 1)").unwrap();
+        assert_eq!(b.to_string(), solver.solve_str("f[(X !-> 1) -> (0 !-> Y)] <=> (0 <-> 1)").unwrap());
+        assert_eq!((b.edges[0].object.clone(), b.edges[0].n), (Some(conv("f")), 0));
+
+        let json = json::solution(&a);
+        assert!(json.starts_with("{\"faces\":[\"(A <-> B)[(A <-> C) -> (B <-> C)]\"],\"edges\":["));
+        assert!(json.contains("{\"face\":0,\"edge\":\"bottom\",\"source\":\"B\",\"target\":\"C\",\
+            \"object\":null,\"input\":\"Dir\",\"output\":\"Iso\",\"n\":1,\"swapped\":true}"));
+        assert!(json.ends_with("],\"diagnostics\":[],\"corrections\":[{\"face\":0,\"edge\":\"bottom\",\
+            \"original\":\"C <- B\",\"corrected\":\"B -> C\"}]}"));
+
+        let mut w = String::new();
+        json::string(&mut w, "a\"b\\c\n");
+        assert_eq!(w, "\"a\\\"b\\\\c\\n\"");
+        assert_eq!(json::error(&Error::Solver("x".into())), "{\"error\":\"Solver failed: x\"}");
+    }
+
+    #[test]
+    fn tikz() {
+        use error::Shape;
+
+        let a = tikz::expr(&conv("(A <-> B)[(A <-> C) -> (B -> D)] <=> (C -> D)")).unwrap();
+        assert_eq!(a, "\\begin{tikzcd}\n\
+            A \\arrow[d, \"\\sim\"] \\arrow[r, \"\\sim\"] & C \\arrow[d] \\\\\n\
+            B \\arrow[r] & D\n\
+            \\end{tikzcd}\n");
+        let a = tikz::expr(&conv("f[(X !-> Y) -> (Z ->> W)] <=> (W <-! Y)")).unwrap();
+        assert_eq!(a, "\\begin{tikzcd}\n\
+            X \\arrow[d, \"f\"] \\arrow[r, hook] & Y \\arrow[d, hook] \\\\\n\
+            Z \\arrow[r, two heads] & W\n\
+            \\end{tikzcd}\n");
+        assert_eq!(tikz::expr(&conv("(A -> B)[(C -> D) -> (B -> D)] <=> (C -> D)")),
+            Err(Error::Shape(Shape::Disconnected)));
+
+        // Reversed zero edges of a solved square are drawn as zero.
+        let y = code::Solver::shared().unwrap().solve("(X <> Y)[(X !-> 1) -> (0 !-> Y)] <=> (0 <-> 1)").unwrap();
+        let a = tikz::expr(&y.faces[0]).unwrap();
+        assert!(a.contains("0 \\arrow[l, \"0\"] \\arrow[u, \"0\"]"));
+        // Named isomorphisms have a single label, and names are escaped.
+        let a = tikz::expr(&conv("(f_1 : A <-> B)[(A -> C) -> (B -> D)] <=> (C -> D)")).unwrap();
+        assert!(a.contains("A \\arrow[d, \"f\\_1\\,\\sim\"] \\arrow[r]"));
+        let a = tikz::expr(&conv("(A -> B)[(A -> C) -> (B -> D&E)] <=> (C -> D&E)")).unwrap();
+        assert!(a.contains("\\\\\nB \\arrow[r] & D\\&E\n"));
+    }
+
+    #[test]
+    fn dot() {
+        let solver = code::Solver::shared().unwrap();
+        let x = "(A <-> B)[(A ->> C) -> (B -> D)] <=> (C <- D)";
+        assert_eq!(dot::export_str(solver, x, false).unwrap(), "digraph {\n\
+            \x20   \"A\" -> \"B\" [label=\"iso\", dir=both];\n\
+            \x20   \"A\" -> \"C\" [label=\"epi\", arrowhead=normalnormal];\n\
+            \x20   \"B\" -> \"D\" [label=\"mor\"];\n\
+            \x20   \"D\" -> \"C\" [label=\"mor\"];\n\
+            }\n");
+
+        let a = dot::export_str(solver, x, true).unwrap();
+        // Input relations.
+        assert!(a.contains("    \"D\" -> \"C\" [label=\"mor\"];\n"));
+        // Derived relations.
+        assert!(a.contains("    \"B\" -> \"C\" [label=\"epi\", arrowhead=normalnormal, color=blue, fontcolor=blue];\n"));
+        assert!(!a.contains("\"A\" -> \"A\""));
+
+        // Edges that are objects are left out.
+        let a = dot::export_str(solver, "f[(X -> Y) -> (Z -> W)] <=> (Y -> W)", false).unwrap();
+        assert!(!a.contains("f"));
+    }
+
+    #[test]
+    fn render() {
+        use render::{ASCII, UNICODE};
+
+        let sq = code::Square::new(&conv("(A <-> B)[(A <-> C) -> (B -> D)] <=> (C -> D)")).unwrap();
+        assert_eq!(render::square(&sq, &UNICODE).unwrap(), "\
+            A ──iso──→ C\n\
+            │          │\n\
+            iso        │\n\
+            ↓          ↓\n\
+            B ───────→ D\n");
+
+        let sq = code::Square::new(&conv("f[(X !-> Y) -> (Zed ->> W)] <=> (Y <- W)")).unwrap();
+        assert_eq!(render::square(&sq, &ASCII).unwrap(), "\
+            X   --mono--> Y\n\
+            |             ^\n\
+            f             |\n\
+            |             |\n\
+            Zed --epi---> W\n");
+    }
+
+    #[test]
+    fn rule_files() {
+        use code::Solver;
+        use std::path::Path;
+
+        let assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+        let a = Solver::from_rules("import \"cat.txt\"", &assets).unwrap();
+        assert_eq!(a.rules, Solver::new().unwrap().rules);
+        let b = Solver::from_file(assets.join("cat.txt")).unwrap();
+        assert_eq!(b.rules, a.rules);
+        assert!(Solver::from_rules("(X, mor'(Y)) :-", &assets).is_err());
+
+        let mut c = Solver::new().unwrap();
+        c.extend_file(assets.join("balanced.txt")).unwrap();
+        let x = "(A ->> B)[(C ->> A) -> (B ->> D)] <=> (D ->> C)";
+        assert_eq!(Solver::new().unwrap().solve_str(x).unwrap(),
+            "(A !->> B)[(A <<-! C) -> (B !->> D)] <=> (C <<-! D)");
+        assert_eq!(c.solve_str(x).unwrap(), "(A <-> B)[(A <-> C) -> (B <-> D)] <=> (C <-> D)");
+
+        assert_eq!(c.extend("(X, split'(Y)) :- (X, iso'(Y)).", &assets),
+            Err(Error::Avatar(Arc::new("split".into()))));
+    }
+
     #[test]
     fn format() {
         fn check(a: &str) {
@@ -491,6 +1187,410 @@ mod tests {
         check("(A -> B)[(A -> C) -> (B -> D)] <=> (C -> D)");
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        fn check(a: Expr) {
+            let json = serde_json::to_string(&a).unwrap();
+            assert_eq!(serde_json::from_str::<Expr>(&json).unwrap(), a);
+        }
+
+        check(conv("0"));
+        check(conv("X <=-> Y"));
+        check(conv("(A -> B)[(A -> C) -> (B -> D)] <=> (C -> D)"));
+        check(conv("f[(X !-> 1) -> (0 !-> Y)] <=> (0 <-> 1)"));
+        check(conv("(f : A -> B)[(A -> C) -> (B -> D)] <=> (C -> D)"));
+        // Morphisms and levels that can not be written in Caso notation.
+        check(Mor(Unknown, 3, Arc::new((conv("X"), _0))));
+        check(Mor(RevZero, 2, Arc::new((conv("X"), conv("Y")))));
+
+        let json = serde_json::to_string(&Mor(Unknown, 1, Arc::new((conv("X"), conv("Y"))))).unwrap();
+        assert_eq!(json, r#"{"Mor":["Unknown",1,[{"Obj":"X"},{"Obj":"Y"}]]}"#);
+
+        let sq = |x: &str| code::Square::new(&conv(x)).unwrap();
+        // Edges in the wrong direction are stored with swapped labels.
+        let swapped = sq("(A -> B)[(C -> A) -> (B -> D)] <=> (D -> C)");
+        assert_eq!(swapped.code, [Dir, RevDir, Dir, RevDir]);
+        for sq in [sq("f[(X !-> 1) -> (0 !-> Y)] <=> (0 <-> 1)"), swapped] {
+            let json = serde_json::to_string(&sq).unwrap();
+            assert_eq!(serde_json::from_str::<code::Square>(&json).unwrap(), sq);
+        }
+    }
+
+    #[test]
+    fn unicode() {
+        fn check(a: &str, b: &str) {
+            let x: Expr = conv(a);
+            let y: Expr = conv(b);
+            assert_eq!(x, y);
+            assert_eq!(&format!("{}", x.unicode()), b);
+            assert_eq!(&format!("{}", y), a);
+        }
+
+        check("X -> Y", "X → Y");
+        check("X <- Y", "X ← Y");
+        check("X ->> Y", "X ↠ Y");
+        check("X <<- Y", "X ↞ Y");
+        check("X !-> Y", "X ↣ Y");
+        check("X <-! Y", "X ↢ Y");
+        check("X <->> Y", "X ⇄ Y");
+        check("X <<-> Y", "X ⇆ Y");
+        check("X !->> Y", "X ⤖ Y");
+        check("X <<-! Y", "X ⬻ Y");
+        check("X <-> Y", "X ≅ Y");
+        check("X <> Y", "X ↮ Y");
+        check("X => Y", "X ⇒ Y");
+        check("X <= Y", "X ⇐ Y");
+        check("X <=> Y", "X ⇔ Y");
+        check("X =-> Y", "X ⇛ Y");
+        check("X <=- Y", "X ⇚ Y");
+        check("(A -> B)[(A -> C) -> (B -> D)] <=> (C -> D)",
+              "(A → B)[(A → C) → (B → D)] ⇔ (C → D)");
+        check("f[(X !-> 1) -> (0 !-> Y)] <=> (0 <-> 1)",
+              "f[(X ↣ 1) → (0 ↣ Y)] ⇔ (0 ≅ 1)");
+
+        // Alternative arrow for iso.
+        assert_eq!(conv("X ↔ Y"), conv("X <-> Y"));
+        // Higher morphisms without Unicode arrows use ASCII.
+        assert_eq!(format!("{}", conv("X !=> Y").unicode()), "X !=> Y");
+
+        let a = solve_str("(A ≅ B)[(A ≅ C) → (B → D)] ⇔ (C → D)").unwrap();
+        assert_eq!(a, solve_str("(A <-> B)[(A <-> C) -> (B -> D)] <=> (C -> D)").unwrap());
+
+        // Unicode arrows do not need whitespace.
+        assert_eq!(conv("(A≅B)"), conv("A <-> B"));
+        assert_eq!(conv("(A≅B)[(A≅C)→(B→D)]⇔(C→D)"), conv("(A <-> B)[(A <-> C) -> (B -> D)] <=> (C -> D)"));
+        // Input after Unicode arrows is checked like for ASCII arrows.
+        let end = |range| Error::Syntax {range, message: "Expected end".into(), suggestion: None};
+        assert_eq!(parsing::parse_str("(A → B)[(A → C) → (B → D)] ⇔ (C → D) junk"), Err(end(49..49)));
+        assert_eq!(parsing::parse_str("A → B → C → D → E xyz"), Err(end(26..26)));
+        assert!(matches!(parsing::parse_str("(A ≅ B)[(A ≅ C) → (B → D)] ⇔ (C →"),
+            Err(Error::Syntax {message, ..}) if message == "Unclosed `(`"));
+        assert!(parsing::parse_str("(A → B)[(A → C) → (B → D)] ⇔ (C → D)  ").is_ok());
+
+        // Both notations round-trip every morphism, including reversed iso and zero.
+        let all = [
+            Dir, RevDir, Mono, RevMono, Epi, RevEpi, EpiMono, RevEpiMono,
+            RightInv, RevRightInv, Iso, RevIso, Zero, RevZero,
+        ];
+        for m in all {
+            let e = Mor(m, 1, Arc::new((conv("X"), conv("Y"))));
+            let a: Expr = conv(&e.to_string());
+            let b: Expr = conv(&e.unicode().to_string());
+            assert_eq!(a, b, "{:?}", m);
+            assert_eq!(a.to_string(), e.to_string(), "{:?}", m);
+            assert_eq!(b.unicode().to_string(), e.unicode().to_string(), "{:?}", m);
+        }
+        let a = "(X <> Y)[(X !-> 1) -> (0 !-> Y)] <=> (0 <-> 1)";
+        let y = code::Solver::shared().unwrap().solve(a).unwrap();
+        assert_eq!(y.to_string(), "(X <> Y)[(X <> 1) -> (Y <> 0)] <=> (1 <> 0)");
+        assert_eq!(y.unicode(), "(X ↮ Y)[(X ↮ 1) → (Y ↮ 0)] ⇔ (1 ↮ 0)");
+    }
+
+    #[test]
+    fn names() {
+        use code::{Diagram, Square};
+
+        let a = conv("f : A -> B");
+        assert_eq!(a, named("f", dir(conv("A"), conv("B"))));
+        assert_eq!(a.name().map(|x| x.as_str()), Some("f"));
+        assert_eq!(a.unnamed(), &conv("A -> B"));
+        assert_eq!(format!("{}", conv("(f: A -> B)[(A -> C) -> (g:B -> D)] <=> (C -> D)")),
+            "(f : A -> B)[(A -> C) -> (g : B -> D)] <=> (C -> D)");
+
+        let sq = Square::new(&conv("(f : A -> B)[(A -> C) -> (g : D <- B)] <=> (C -> D)")).unwrap();
+        assert_eq!(sq.labels, Square::new(&conv("(A -> B)[(A -> C) -> (D <- B)] <=> (C -> D)")).unwrap().labels);
+        assert_eq!(sq.names, [Some(Arc::new("f".into())), None, Some(Arc::new("g".into())), None]);
+
+        let a = &solve_str("(f : A <-> B)[(g : A <-> C) -> (h : B <-> D)] <=> (k : D -> C)").unwrap();
+        assert_eq!(a, "(f : A <-> B)[(g : A <-> C) -> (h : B <-> D)] <=> (k : C <-> D)");
+        let a = &solve_str("(f : A <-> B)[(A <-> C) -> (g : B -> C)]").unwrap();
+        assert_eq!(a, "(f : A <-> B)[(A <-> C) -> (g : B <-> C)]");
+
+        // Parallel arrows with different names are different edges.
+        let faces = parsing::Parser::shared().unwrap()
+            .parse_faces("(A -> B)[(A -> C) -> (f : B -> D)] <=> (C -> D); \
+                (A -> B)[(A -> C) -> (g : B -> D)] <=> (C -> D)").unwrap();
+        let d = Diagram::new(&faces).unwrap();
+        assert_eq!(d.code.len(), 5);
+        assert_ne!(d.faces[0].edges[2], d.faces[1].edges[2]);
+
+        let y = tikz::expr(&conv("(f : A -> B)[(A -> C) -> (B -> D)] <=> (C -> D)")).unwrap();
+        assert!(y.contains("A \\arrow[d, \"f\"]"));
+    }
+
+    #[test]
+    fn composition() {
+        use code::{Diagram, Square};
+        use error::Shape;
+
+        let a = conv("A -> B -> C");
+        assert_eq!(a, comp(dir(conv("B"), conv("C")), dir(conv("A"), conv("B"))));
+        assert_eq!((a.source(), a.target()), (&conv("A"), &conv("C")));
+        assert_eq!(format!("{}", a), "A -> B -> C");
+        assert_eq!(format!("{}", conv("A !-> B <- C ->> D")), "A !-> B <- C ->> D");
+        assert_eq!(format!("{}", conv("(g : B -> C) ∘ (f:A -> B)")), "(g : B -> C) ∘ (f : A -> B)");
+        assert_eq!(format!("{}", conv("(B -> C) ∘ (A -> B)")), "A -> B -> C");
+        assert_eq!(format!("{}", conv("A -> (B -> C)")), "A -> (B -> C)");
+        assert_eq!(format!("{}", conv("A → B ↠ C").unicode()), "A → B ↠ C");
+
+        // A composition is an edge from source to target.
+        let sq = Square::new(&conv("(A -> B)[(A -> X -> C) -> (B -> D)] <=> (C -> D)")).unwrap();
+        assert_eq!(sq.labels[1], [0, 1, 3]);
+        assert_eq!(sq.code, [Dir; 4]);
+        assert_eq!(Square::new(&conv("(A -> B)[(A -> X <- C) -> (B -> D)] <=> (C -> D)")),
+            Err(Error::Shape(Shape::Composition)));
+        assert_eq!(Square::new(&conv("(A -> B)[((Y -> C) ∘ (A -> X)) -> (B -> D)] <=> (C -> D)")),
+            Err(Error::Shape(Shape::Composition)));
+
+        let faces = [conv("(A -> B)[(C <- X <- A) -> (B -> D)] <=> (C -> D)")];
+        let d = Diagram::new(&faces).unwrap();
+        assert_eq!(d.faces[0].parts.len(), 2);
+        assert_eq!(d.comps, [[1, 5, 3]]);
+
+        // If `g ∘ f` is mono, then `f` is mono.
+        let a = &solve_str("(A <-> B)[(A -> X -> C) -> (B !-> D)] <=> (C <-> D)").unwrap();
+        assert_eq!(a, "(A <-> B)[(A !-> X -> C) -> (B !-> D)] <=> (C <-> D)");
+        let a = &solve_str("(A <-> B)[(C <- X <- A) -> (B !-> D)] <=> (C <-> D)").unwrap();
+        assert_eq!(a, "(A <-> B)[(C <- X <-! A) -> (B !-> D)] <=> (C <-> D)");
+        // If `g ∘ f` is epi, then `g` is epi.
+        let a = &solve_str("(A <-> B)[((g : X -> C) ∘ (f : A -> X)) -> (B ->> D)] <=> (C <-> D)").unwrap();
+        assert_eq!(a, "(A <-> B)[((g : X ->> C) ∘ (f : A -> X)) -> (B ->> D)] <=> (C <-> D)");
+        // Morphisms of the square are not cancelled.
+        let a = &solve_str("(A !-> B)[(A -> C) -> (B !-> D)] <=> (C -> D)").unwrap();
+        assert_eq!(a, "(A !-> B)[(A -> C) -> (B !-> D)] <=> (C -> D)");
+    }
+
+    #[test]
+    fn infer() {
+        use code::{Solver, Square, Triangle};
+
+        let sq = Square::new(&conv("f[(A <-> C) -> (B <-> D)] <=> (C -> D)")).unwrap();
+        assert_eq!(sq.code[0], Unknown);
+        assert_eq!(sq.eval()[0], Dir);
+        let ex = sq.explain().unwrap();
+        assert_eq!((ex[0].edge, ex[0].from, ex[0].to), (0, Unknown, Dir));
+
+        let a = &solve_str("f[(A <-> C) -> (B <-> D)] <=> (C -> D)").unwrap();
+        assert_eq!(a, "(f : A -> B)[(A <-> C) -> (B <-> D)] <=> (C -> D)");
+        let a = &solve_str("f[(A <-> C) -> (B <-> D)] <=> (C <-> D)").unwrap();
+        assert_eq!(a, "(f : A <-> B)[(A <-> C) -> (B <-> D)] <=> (C <-> D)");
+        // Prefers the direction of the face, but infers the opposite direction when needed.
+        let a = &solve_str("f[(A <-> C) -> (B <-> D)] <=> (C <- D)").unwrap();
+        assert_eq!(a, "(f : A <- B)[(A <-> C) -> (B <-> D)] <=> (C <- D)");
+        let a = &solve_str("(A <-> B)[g -> (B ->> D)] <=> (C <-> D)").unwrap();
+        assert_eq!(a, "(A <-> B)[(g : A ->> C) -> (B ->> D)] <=> (C <-> D)");
+        let tr = Triangle::new(&conv("(A -> B)[h -> (B <-> C)]")).unwrap();
+        assert_eq!(tr.eval(), [Dir, Dir, Iso]);
+        let a = &solve_str("(A -> B)[(A <-> C) -> (B -> C)]; f[(B -> C) -> (D <-> C)]").unwrap();
+        assert_eq!(a, "(A -> B)[(A <-> C) -> (B -> C)]; (f : B -> D)[(B -> C) -> (D <-> C)]");
+
+        // Nothing can be inferred.
+        let a = Solver::shared().unwrap().solve("f[(A -> C) -> (B -> D)] <=> (C -> D)").unwrap();
+        assert_eq!(a.to_string(), "f[(A -> C) -> (B -> D)] <=> (C -> D)");
+        assert_eq!(a.diagnostics, [Error::Uninferred(conv("f"))]);
+        assert_eq!(a.edges[0].output, Unknown);
+    }
+
+    #[test]
+    fn query() {
+        use code::Solver;
+        use proof::Derivation;
+
+        let solver = Solver::shared().unwrap();
+        let sq = "(A !-> B)[(A -> C) -> (B !-> D)] <=> (C -> D)";
+        let a = solver.query_str(&format!("{} |- A !-> D", sq)).unwrap();
+        assert!(a.holds());
+        assert_eq!(a.goal, conv("A !-> D"));
+        let d = &a.derivations.as_ref().unwrap()[0];
+        assert_eq!(d.fact().to_string(), "(A, mono'(D))");
+        assert!(matches!(d, Derivation::Rule {premises, ..} if premises.len() == 2));
+        assert!(a.to_string().starts_with("yes: A !-> D\n  (A, mono'(D)) by "));
+
+        // Reversed goals and relations that hold as premises.
+        let a = solver.query_str(&format!("{} |- D <- C", sq)).unwrap();
+        assert!(matches!(a.derivations.as_deref(), Some([Derivation::Premise(_)])));
+        // Epi-mono needs both relations.
+        assert!(!solver.query_str(&format!("{} |- A !->> D", sq)).unwrap().holds());
+        let a = solver.query_str("(A <-> B)[(A <-> C) -> (B <-> D)] <=> (C -> D) |- C <-> D").unwrap();
+        assert!(a.holds());
+        let a = solver.query_str("(A -> B)[(A -> C) -> (B -> D)] <=> (C -> D) |- A <> D").unwrap();
+        assert_eq!(a.to_string(), "no: A <> D\n");
+
+        assert_eq!(solver.query_str(&format!("{} |- A -> X", sq)), Err(Error::Goal(conv("A -> X"))));
+        assert_eq!(solver.query_str(&format!("{} |- A", sq)), Err(Error::Goal(conv("A"))));
+        assert!(matches!(solver.query_str(sq), Err(Error::Syntax {..})));
+        let err = solver.query_str("(A -> B)[(A -> C) -> (B -> D)] <=> (C -> D) |- A <--/ D").unwrap_err();
+        assert_eq!(err.range(), Some(49..53));
+    }
+
+    #[test]
+    fn session() {
+        use code::Solver;
+        use session::Session;
+
+        let solver = Solver::shared().unwrap();
+        let mut s = Session::new();
+        assert_eq!(s.assume(solver, "B ->> D").unwrap(), [conv("B ->> D")]);
+        let a = s.solve(solver, "(A <-> B)[(A <-> C) -> (B -> D)] <=> (C -> D)").unwrap();
+        assert_eq!(a.to_string(), "(A <-> B)[(A <-> C) -> (B ->> D)] <=> (C ->> D)");
+        assert_eq!(a.edges.len(), 4);
+        assert!(s.query_str(solver, "|- B ->> D").unwrap().holds());
+
+        // Faces are solved before they are assumed, and used by later inputs.
+        let a = s.assume(solver, "(A <-> B)[(A <-> C) -> (B -> D)] <=> (C -> D)").unwrap();
+        assert_eq!(a, [conv("(A <-> B)[(A <-> C) -> (B ->> D)] <=> (C ->> D)")]);
+        assert!(s.query_str(solver, "|- C ->> D").unwrap().holds());
+        assert_eq!(s.hypotheses.len(), 2);
+
+        // Higher morphisms and compositions are edges.
+        let mut t = Session::new();
+        assert_eq!(t.assume(solver, "A !-> B !-> C; X => Y").unwrap(), [conv("A !-> B !-> C"), conv("X => Y")]);
+        assert!(t.query_str(solver, "|- A !-> C").unwrap().holds());
+        assert!(t.query_str(solver, "|- X -> Y").unwrap().holds());
+        assert!(t.assume(solver, "(C -> D) ∘ (A -> B)").is_err());
+        assert_eq!(t.hypotheses.len(), 2);
+
+        // Removes the file when the test ends, also when it fails.
+        struct Remove(std::path::PathBuf);
+        impl Drop for Remove {
+            fn drop(&mut self) {let _ = std::fs::remove_file(&self.0);}
+        }
+
+        // Unique per process, such that concurrent test runs do not share the file.
+        let file = std::env::temp_dir().join(format!("caso-session-test-{}.txt", std::process::id()));
+        let _remove = Remove(file.clone());
+        s.save(&file).unwrap();
+        assert_eq!(Session::load(&file).unwrap(), s);
+        std::fs::remove_file(&file).unwrap();
+        assert!(matches!(Session::load(&file), Err(Error::Io(_))));
+
+        // Zero edges keep their direction when saved.
+        let mut z = Session::new();
+        let a = z.assume(solver, "(X <> Y)[(X !-> 1) -> (0 !-> Y)] <=> (0 <-> 1); Y <> X").unwrap();
+        assert_eq!(a[0], conv("Y <> X"));
+        assert_eq!(a[1].to_string(), "(X <> Y)[(X <> 1) -> (0 <> Y)] <=> (0 <> 1)");
+        z.save(&file).unwrap();
+        assert_eq!(Session::load(&file).unwrap(), z);
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(s.retract(0), Some(conv("B ->> D")));
+        assert_eq!(s.retract(1), None);
+        assert!(s.query_str(solver, "|- C ->> D").unwrap().holds());
+        s.retract(0);
+        assert!(!s.query_str(solver, "(A -> B)[(A -> C) -> (B -> D)] <=> (C -> D) |- C ->> D").unwrap().holds());
+    }
+
+    #[test]
+    fn contradiction() {
+        use code::{Square, Solver, Triangle};
+
+        let err = |face, edges: &[usize]| Error::Contradiction {face, edges: edges.to_vec()};
+        let sq = Square::new(&conv("(A -> B)[(A -> C) -> (B -> D)] <=> (C -> E)")).unwrap();
+        assert_eq!(sq.try_eval(), Err(err(0, &[2, 3])));
+        assert_eq!(sq.eval(), sq.code);
+        let tr = Triangle::new(&conv("(A -> B)[(A -> C) -> (D -> C)]")).unwrap();
+        assert_eq!(tr.try_eval(), Err(err(0, &[0, 2])));
+        assert_eq!(solve_str("(A -> B)[(C -> D) -> (E -> F)] <=> (G -> H)"), Err(err(0, &[0, 1])));
+        assert_eq!(solve_str("(A -> B)[(A -> C) -> (B -> C)]; (B -> C)[(B -> D) -> (E -> F)]"),
+            Err(err(1, &[0, 2])));
+        let solver = Solver::shared().unwrap();
+        let x = "(A -> B)[(A -> C) -> (B -> D)] <=> (C -> E)";
+        assert_eq!(solver.solve(x).unwrap_err(), err(0, &[2, 3]));
+        assert_eq!(solver.query_str(&format!("{} |- A -> D", x)).unwrap_err(), err(0, &[2, 3]));
+        assert_eq!(err(0, &[2, 3]).to_string(),
+            "Contradiction in face 0: Edges `bottom`, `right` do not meet at the same corners in any direction");
+
+        // Edges in the wrong direction are corrected instead.
+        assert!(solve_str("(A -> B)[(B -> C) -> (A -> D)] <=> (C -> D)").is_ok());
+        assert!(solve_str("(A <-> B)[(A <-> C) -> (B <-> D)] <=> (D -> C)").is_ok());
+        assert!(solve_str("f[(X !-> 1) -> (0 !-> Y)] <=> (0 <-> 1)").is_ok());
+        // Edges that are objects are not checked.
+        assert!(solve_str("f[(A -> C) -> g] <=> (C -> D)").is_ok());
+
+        // Rules may derive contradictions, e.g. when `1` is not a zero object.
+        let rules = format!("{}\n(1, contradiction'(1)) :- (1, zero'(Y)).", include_str!("../assets/cat.txt"));
+        let solver = Solver::from_rules(&rules, std::path::Path::new("")).unwrap();
+        let inconsistent = |edges: &[(usize, usize)]| Error::Inconsistent {
+            fact: "(1, contradiction'(1))".into(),
+            edges: edges.to_vec(),
+        };
+        let x = "(X <> Y)[(X !-> 1) -> (0 !-> Y)] <=> (0 <-> 1)";
+        assert_eq!(solver.solve_str(x), Err(inconsistent(&[(0, 3)])));
+        assert_eq!(solver.solve(x).unwrap_err(), inconsistent(&[(0, 3)]));
+        assert_eq!(solver.query_str(&format!("{} |- X -> Y", x)).unwrap_err(), inconsistent(&[(0, 3)]));
+        assert!(solver.solve_str("(A -> B)[(A -> 1) -> (B -> D)] <=> (1 -> D)").is_ok());
+        assert_eq!(solver.solve_str("(X <> Y)[(X -> 1) -> (Y -> D)] <=> (1 -> D)"), Err(inconsistent(&[(0, 0), (0, 2), (0, 3)])));
+        let y = "(A -> B)[(A -> C) -> (B -> 0)] <=> (C -> 0); (0 <-> 1)[(0 -> D) -> (1 -> D)]";
+        assert_eq!(solver.solve_str(y), Err(inconsistent(&[(1, 1), (1, 2)])));
+        assert_eq!(inconsistent(&[(0, 3), (1, 0)]).to_string(),
+            "Contradiction `(1, contradiction'(1))` follows from edges `right` in face 0, `left` in face 1");
+        let mut session = session::Session::new();
+        session.assume(&solver, "(A <-> B)[(A <-> C) -> (B -> D)] <=> (C -> D)").unwrap();
+        assert_eq!(session.solve(&solver, "(0 <-> 1)[(0 -> D) -> (1 -> D)]"), Err(inconsistent(&[(0, 1), (0, 2)])));
+    }
+
+    #[test]
+    fn corrections() {
+        use code::{Correction, Solver};
+
+        let x = "(a -> b)[(c -> a) -> (b -> d)] <=> (c -> d)";
+        let c = Correction {face: 0, edge: 1, original: conv("c -> a"), corrected: conv("a <- c")};
+        let mut solver = Solver::shared().unwrap().clone();
+        let y = solver.solve(x).unwrap();
+        assert_eq!(y.corrections, vec![c.clone()]);
+        assert!(y.edges[1].swapped);
+        assert_eq!(c.to_string(), "Corrected `top` edge in face 0 from `c -> a` to `a <- c`");
+        assert!(solver.solve("(a -> b)[(a <- c) -> (b -> d)] <=> (c -> d)").unwrap().corrections.is_empty());
+        // Left edges written in reverse direction are not corrected.
+        assert!(solver.solve("(C <- B)[(B -> A) -> (C -> A)]").unwrap().corrections.is_empty());
+        let y = solver.solve("(A <-> B)[(A <-> C) -> (B -> C)]; (B -> C)[(D <-> B) -> (C -> D)]").unwrap();
+        assert_eq!((y.corrections.len(), y.corrections[0].face, y.corrections[0].edge), (1, 1, 1));
+        assert_eq!(y.corrections[0].corrected.to_string(), "B <-> D");
+        let named = solver.solve("(f : A <-> B)[(A <-> C) -> (B <-> D)] <=> (k : D -> C)").unwrap();
+        assert_eq!(named.corrections[0].corrected, conv("k : C <- D"));
+
+        solver.strict = true;
+        assert_eq!(solver.solve(x), Err(Error::Correction(c.clone())));
+        assert_eq!(solver.solve_str(x), Err(Error::Correction(c.clone())));
+        assert_eq!(solver.query_str(&format!("{} |- a -> d", x)).unwrap_err(), Error::Correction(c.clone()));
+        assert_eq!(solver.solve_str("(a -> b)[(a <- c) -> (b -> d)] <=> (c -> d)"),
+            Ok("(a -> b)[(a <- c) -> (b -> d)] <=> (c -> d)".into()));
+        let session = session::Session::new();
+        assert_eq!(session.solve(&solver, x), Err(Error::Correction(c)));
+    }
+
+    #[test]
+    fn canonical() {
+        use code::{equivalent, Square};
+
+        let sq = |x: &str| Square::new(&conv(x)).unwrap();
+        let a = sq("(A -> B)[(A -> C) -> (B -> D)] <=> (C -> D)");
+        assert_eq!(a.canonical(), Some(sq("(A -> B)[(A -> C) -> (B -> D)] <=> (C -> D)")));
+        // Transposed, reflected and rotated.
+        for x in [
+            "(A -> C)[(A -> B) -> (C -> D)] <=> (B -> D)",
+            "(C -> D)[(C <- A) -> (D <- B)] <=> (A -> B)",
+            "(D <- C)[(D <- B) -> (C <- A)] <=> (B <- A)",
+        ] {
+            assert_eq!(sq(x).canonical(), a.canonical());
+            assert!(equivalent(&sq(x), &a));
+        }
+        // Iso and zero hold in both directions.
+        assert!(equivalent(&sq("(A <-> B)[(A <-> C) -> (B <> D)] <=> (C <-> D)"),
+            &sq("(D <-> C)[(D <> B) -> (C <-> A)] <=> (B <-> A)")));
+        assert!(!equivalent(&a, &sq("(A !-> B)[(A -> C) -> (B -> D)] <=> (C -> D)")));
+        assert!(!equivalent(&a, &sq("(A -> B)[(A -> C) -> (B -> D)] <=> (C -> E)")));
+        assert!(!equivalent(&sq("(f : A -> B)[(A -> C) -> (B -> D)] <=> (C -> D)"),
+            &sq("(A -> B)[(f : A -> C) -> (B -> D)] <=> (C -> D)")));
+        assert!(equivalent(&sq("(f : A -> B)[(A -> C) -> (B -> D)] <=> (C -> D)"),
+            &sq("(A -> C)[(f : A -> B) -> (C -> D)] <=> (B -> D)")));
+        assert_eq!(sq("(A -> B)[(C -> D) -> (E -> F)] <=> (G -> H)").canonical(), None);
+        // Edges that are objects keep their position relative to the corners.
+        assert!(equivalent(&sq("f[(A -> C) -> (B -> D)] <=> (C -> D)"),
+            &sq("(A -> C)[f -> (C -> D)] <=> (B -> D)")));
+    }
+
     #[test]
     fn rewrite() {
         // `(C -> D) => (C <-> D)`.
@@ -0,0 +1,249 @@
+//! Command line interface for Caso.
+//!
+//! Exit codes: 0 on success, 1 if some expression failed or did not match, 2 on usage errors.
+
+use caso::code::Solver;
+
+use std::io::Read;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage:
+    caso solve [options] [<expr>...]   solves expressions, one per line from stdin if none
+    caso check [options] <file>...     checks expected output of expressions in files, `-` for stdin
+    caso tikz [options] [<expr>...]    prints solved squares as LaTeX tikz-cd, one per line from stdin if none
+    caso dot [options] [<expr>...]     prints diagrams in Graphviz DOT format, one per line from stdin if none
+
+Options:
+    --rules <file>           adds rules from an Avalog file
+    --replace-rules <file>   replaces the rule base with rules from an Avalog file
+    --format <text|json>     output format of `solve`, one JSON object per line for `json`
+    --knowledge              makes `dot` print every relation known by the solver
+    --unicode                makes `solve` print results using Unicode arrows
+    --strict                 fails instead of correcting the direction of edges
+
+A checked file contains inputs `> <expr>`, each followed by the expected output line.
+Use `ERROR` as expected output when the expression should fail.
+Empty lines and lines starting with `//` are ignored.
+";
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let cmd = args.next();
+    let (solver, flags, rest) = match parse_options(args) {
+        Ok(x) => x,
+        Err(err) => return usage(&err),
+    };
+    match cmd.as_deref() {
+        Some("solve") => solve(&solver, &flags, &rest),
+        Some("tikz") => tikz(&solver, &rest),
+        Some("dot") => dot(&solver, flags.knowledge, &rest),
+        Some("check") if rest.is_empty() => usage("Expected file after `check`"),
+        Some("check") => check(&solver, &rest),
+        Some("help" | "--help" | "-h") => {print!("{}", USAGE); ExitCode::SUCCESS}
+        Some(x) => usage(&format!("Unknown command `{}`", x)),
+        None => usage("Expected command"),
+    }
+}
+
+fn usage(err: &str) -> ExitCode {
+    eprintln!("ERROR: {}\n\n{}", err, USAGE);
+    ExitCode::from(2)
+}
+
+// Flags set by options.
+#[derive(Default)]
+struct Flags {
+    json: bool,
+    knowledge: bool,
+    unicode: bool,
+}
+
+// Creates solver from options and returns flags and remaining arguments.
+fn parse_options(
+    mut args: impl Iterator<Item = String>
+) -> Result<(Solver, Flags, Vec<String>), String> {
+    let mut solver = Solver::shared().map_err(|err| err.to_string())?.clone();
+    let mut flags = Flags::default();
+    let mut strict = false;
+    let mut rest = vec![];
+    while let Some(arg) = args.next() {
+        match &*arg {
+            "--rules" | "--replace-rules" => {
+                let file = args.next().ok_or_else(|| format!("Expected file after `{}`", arg))?;
+                if arg == "--rules" {
+                    solver.extend_file(&file).map_err(|err| err.to_string())?;
+                } else {
+                    solver = Solver::from_file(&file).map_err(|err| err.to_string())?;
+                }
+            }
+            "--format" => match args.next().as_deref() {
+                Some("text") => flags.json = false,
+                Some("json") => flags.json = true,
+                _ => return Err("Expected `text` or `json` after `--format`".into()),
+            },
+            "--knowledge" => flags.knowledge = true,
+            "--unicode" => flags.unicode = true,
+            "--strict" => strict = true,
+            _ => rest.push(arg),
+        }
+    }
+    solver.strict = strict;
+    Ok((solver, flags, rest))
+}
+
+fn read(file: &str) -> Result<String, String> {
+    let mut data = String::new();
+    let res = if file == "-" {
+        std::io::stdin().read_to_string(&mut data).map(|_| ())
+    } else {
+        std::fs::File::open(file).and_then(|mut f| f.read_to_string(&mut data)).map(|_| ())
+    };
+    res.map_err(|err| format!("Could not read `{}`: {}", file, err))?;
+    Ok(data)
+}
+
+// Returns expressions from arguments, or lines from stdin if there are none.
+fn exprs(args: &[String]) -> Result<Vec<String>, String> {
+    if args.is_empty() {
+        let input = read("-")?;
+        Ok(input.lines().map(|x| x.trim()).filter(|x| !x.is_empty()).map(String::from).collect())
+    } else {
+        Ok(args.to_vec())
+    }
+}
+
+fn solve(solver: &Solver, flags: &Flags, args: &[String]) -> ExitCode {
+    let exprs = match exprs(args) {
+        Ok(x) => x,
+        Err(err) => return usage(&err),
+    };
+
+    let mut failed = false;
+    for x in &exprs {
+        if flags.json {
+            match solver.solve(x) {
+                Ok(y) => {
+                    println!("{}", caso::json::solution(&y));
+                    // Edges that could not be inferred are notes, as in text mode.
+                    failed |= y.diagnostics.iter().any(|err| !matches!(err, caso::Error::Uninferred(_)));
+                }
+                Err(err) => {
+                    println!("{}", caso::json::error(&err));
+                    failed = true;
+                }
+            }
+            continue;
+        }
+
+        let res = if flags.unicode {
+            solver.solve(x).and_then(|y| {
+                match y.diagnostics.iter().find(|err| !matches!(err, caso::Error::Uninferred(_))) {
+                    Some(err) => Err(err.clone()),
+                    None => Ok(y.unicode()),
+                }
+            })
+        } else {
+            solver.solve_str(x)
+        };
+        match res {
+            Ok(y) => println!("{}", y),
+            Err(err) => {
+                eprintln!("ERROR:\n{}", err.render(x));
+                failed = true;
+            }
+        }
+    }
+    if failed {ExitCode::FAILURE} else {ExitCode::SUCCESS}
+}
+
+fn tikz(solver: &Solver, args: &[String]) -> ExitCode {
+    let exprs = match exprs(args) {
+        Ok(x) => x,
+        Err(err) => return usage(&err),
+    };
+
+    let mut failed = false;
+    for x in &exprs {
+        let res = solver.solve(x).and_then(|y| {
+            match y.diagnostics.iter().find(|err| !matches!(err, caso::Error::Uninferred(_))) {
+                Some(err) => Err(err.clone()),
+                None => y.faces.iter().map(caso::tikz::expr).collect::<Result<String, _>>(),
+            }
+        });
+        match res {
+            Ok(y) => print!("{}", y),
+            Err(err) => {
+                eprintln!("ERROR:\n{}", err.render(x));
+                failed = true;
+            }
+        }
+    }
+    if failed {ExitCode::FAILURE} else {ExitCode::SUCCESS}
+}
+
+fn dot(solver: &Solver, knowledge: bool, args: &[String]) -> ExitCode {
+    let exprs = match exprs(args) {
+        Ok(x) => x,
+        Err(err) => return usage(&err),
+    };
+
+    let mut failed = false;
+    for x in &exprs {
+        match caso::dot::export_str(solver, x, knowledge) {
+            Ok(y) => print!("{}", y),
+            Err(err) => {
+                eprintln!("ERROR:\n{}", err.render(x));
+                failed = true;
+            }
+        }
+    }
+    if failed {ExitCode::FAILURE} else {ExitCode::SUCCESS}
+}
+
+fn check(solver: &Solver, files: &[String]) -> ExitCode {
+    let (mut passed, mut failed) = (0, 0);
+    for file in files {
+        let data = match read(file) {
+            Ok(x) => x,
+            Err(err) => return usage(&err),
+        };
+        let mut lines = data.lines().enumerate()
+            .map(|(i, x)| (i + 1, x.trim()))
+            .filter(|(_, x)| !x.is_empty() && !x.starts_with("//"))
+            .peekable();
+        while let Some((n, line)) = lines.next() {
+            let input = match line.strip_prefix('>') {
+                Some(x) => x.trim(),
+                None => {
+                    eprintln!("{}:{}: Expected input `> <expr>`", file, n);
+                    failed += 1;
+                    continue;
+                }
+            };
+            let expected = match lines.next_if(|(_, x)| !x.starts_with('>')) {
+                Some((_, x)) => x,
+                None => {
+                    eprintln!("{}:{}: Expected output after `{}`", file, n, input);
+                    failed += 1;
+                    continue;
+                }
+            };
+            let ok = match solver.solve_str(input) {
+                Ok(y) if y == expected => true,
+                Ok(y) => {
+                    eprintln!("{}:{}: {}\n  expected: {}\n     found: {}", file, n, input, expected, y);
+                    false
+                }
+                Err(_) if expected.starts_with("ERROR") => true,
+                Err(err) => {
+                    eprintln!("{}:{}: {}\n  expected: {}\n     found: ERROR: {}", file, n, input, expected, err);
+                    false
+                }
+            };
+            if ok {passed += 1} else {failed += 1}
+        }
+    }
+    println!("{} passed, {} failed", passed, failed);
+    if failed > 0 {ExitCode::FAILURE} else {ExitCode::SUCCESS}
+}
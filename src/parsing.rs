@@ -2,7 +2,7 @@
 
 use crate::*;
 
-use piston_meta::{Convert, Range};
+use piston_meta::{parse, syntax_errstr, Convert, Range, Syntax};
 
 fn parse_expr(
     node: &str,
@@ -27,6 +27,9 @@ fn parse_expr(
         } else if let Ok((range, val)) = parse_mor("mor", convert, ignored) {
             convert.update(range);
             expr = Some(val);
+        } else if let Ok((range, val)) = parse_comp("comp", convert, ignored) {
+            convert.update(range);
+            expr = Some(val);
         } else if let Ok((range, val)) = parse_expr("path", convert, ignored) {
             convert.update(range);
             if let Some(obj) = expr {
@@ -54,14 +57,22 @@ fn parse_mor(
     let start_range = convert.start_node(node)?;
     convert.update(start_range);
 
+    let mut name: Option<Arc<String>> = None;
     let mut mor: Option<Morphism> = None;
     let mut n = 0;
     let mut left: Option<Expr> = None;
     let mut right: Option<Expr> = None;
+    let mut then: Option<Expr> = None;
     loop {
         if let Ok(range) = convert.end_node(node) {
             convert.update(range);
             break;
+        } else if let Ok((range, val)) = parse_mor("then", convert, ignored) {
+            convert.update(range);
+            then = Some(val);
+        } else if let Ok((range, val)) = convert.meta_string("name") {
+            convert.update(range);
+            name = Some(val);
         } else if let Ok((range, val)) = parse_expr("left", convert, ignored) {
             convert.update(range);
             left = Some(val);
@@ -116,6 +127,12 @@ fn parse_mor(
         } else if let Ok((range, _)) = convert.meta_bool("2-iso") {
             convert.update(range);
             mor = Some(Iso);
+        } else if let Some((range, &(_, m, k))) = UNICODE.iter()
+            .find_map(|x| convert.meta_bool(x.0).ok().map(|(range, _)| (range, x)))
+        {
+            convert.update(range);
+            mor = Some(m);
+            n = k;
         } else if let Ok((range, _)) = convert.meta_bool("+2") {
             convert.update(range);
             n += 2;
@@ -131,28 +148,262 @@ fn parse_mor(
 
     let mor = mor.ok_or(())?;
     let left = left.ok_or(())?;
-    let right = right.ok_or(())?;
     let n = if mor == Zero {1} else {n};
-    let expr = Mor(mor, n, Arc::new((left, right)));
+    let expr = match then {
+        // Chain `A -> B -> C` composes `A -> B` with `B -> C`.
+        Some(g) => {
+            let f = Mor(mor, n, Arc::new((left, g.source().clone())));
+            Comp(Arc::new((g, f)))
+        }
+        None => Mor(mor, n, Arc::new((left, right.ok_or(())?))),
+    };
+    let expr = match name {
+        Some(name) => Named(name, Arc::new(expr)),
+        None => expr,
+    };
     Ok((convert.subtract(start), expr))
 }
 
+fn parse_comp(
+    node: &str,
+    mut convert: Convert,
+    ignored: &mut Vec<Range>
+) -> Result<(Range, Expr), ()> {
+    let start = convert;
+    let start_range = convert.start_node(node)?;
+    convert.update(start_range);
+
+    let mut left: Option<Expr> = None;
+    let mut right: Option<Expr> = None;
+    loop {
+        if let Ok(range) = convert.end_node(node) {
+            convert.update(range);
+            break;
+        } else if let Ok((range, val)) = parse_expr("left", convert, ignored) {
+            convert.update(range);
+            left = Some(val);
+        } else if let Ok((range, val)) = parse_expr("right", convert, ignored) {
+            convert.update(range);
+            right = Some(val);
+        } else {
+            let range = convert.ignore();
+            convert.update(range);
+            ignored.push(range);
+        }
+    }
+
+    let left = left.ok_or(())?;
+    let right = right.ok_or(())?;
+    Ok((convert.subtract(start), Comp(Arc::new((left, right)))))
+}
+
+/// Parses expressions using the syntax in "assets/syntax.txt".
+///
+/// The syntax is compiled once when creating the parser,
+/// such that it can be reused for parsing many expressions.
+#[derive(Clone, Debug)]
+pub struct Parser {
+    /// Compiled syntax rules.
+    pub syntax: Syntax,
+}
+
+impl Parser {
+    /// Creates a new parser.
+    pub fn new() -> Result<Parser, Error> {
+        let syntax = syntax_errstr(include_str!("../assets/syntax.txt")).map_err(Error::Grammar)?;
+        Ok(Parser {syntax})
+    }
+
+    /// Returns a parser shared between threads, which is created on first use.
+    pub fn shared() -> Result<&'static Parser, Error> {
+        use std::sync::OnceLock;
+
+        static PARSER: OnceLock<Result<Parser, Error>> = OnceLock::new();
+        PARSER.get_or_init(Parser::new).as_ref().map_err(|err| err.clone())
+    }
+
+    /// Parses an expression string.
+    pub fn parse(&self, data: &str) -> Result<Expr, Error> {
+        let mut faces = self.parse_faces(data)?;
+        if faces.len() != 1 {
+            // Point at the separator of the second face.
+            let start = data.find(';').unwrap_or(0);
+            return Err(Error::Conversion {range: start..start + 1});
+        }
+        Ok(faces.swap_remove(0))
+    }
+
+    /// Parses faces of a diagram separated by `;`.
+    pub fn parse_faces(&self, data: &str) -> Result<Vec<Expr>, Error> {
+        let mut meta_data = vec![];
+        // The parser counts the characters of the input to check that it reached the end,
+        // but reads bytes, such that input after multibyte characters, e.g. `→`, is not checked.
+        // Padding with whitespace, which the syntax accepts at the end, makes the counts match.
+        let padded = format!("{}{}", data, " ".repeat(data.len() - data.chars().count()));
+        if let Err(err) = parse(&self.syntax, &padded, &mut meta_data) {
+            // Remove debug id from message, e.g. `#3, Expected: ...`.
+            let message = format!("{}", err.data);
+            let message = match message.split_once(", ") {
+                Some((id, msg)) if id.starts_with('#') => msg.into(),
+                _ => message,
+            };
+            let start = err.offset.min(data.len());
+            let range = start..(err.offset + err.length).min(data.len());
+            return Err(brackets(data).or_else(|| arrow(data, err.offset)).unwrap_or(Error::Syntax {
+                range,
+                message,
+                suggestion: None,
+            }));
+        }
+
+        // piston_meta::json::print(&meta_data);
+
+        let mut convert = Convert::new(&meta_data);
+        let mut ignored = vec![];
+        let mut faces = vec![];
+        while let Ok((range, expr)) = parse_expr("expr", convert, &mut ignored) {
+            convert.update(range);
+            faces.push(expr);
+        }
+        if faces.is_empty() || convert.remaining_data_len() != 0 {
+            // Point at the first meta data that could not be converted.
+            let range = ignored.first()
+                .and_then(|r| Some((meta_data.get(r.offset)?, meta_data.get(r.offset + r.length - 1)?)))
+                .map(|(a, b)| a.offset..b.offset + b.length)
+                .unwrap_or(0..data.len());
+            return Err(Error::Conversion {range});
+        }
+        Ok(faces)
+    }
+
+    /// Parses a query `<diagram> |- <goal>`, returning the faces of the diagram and the goal.
+    ///
+    /// The diagram may be empty, e.g. `|- A -> C`.
+    pub fn parse_query(&self, data: &str) -> Result<(Vec<Expr>, Expr), Error> {
+        let Some((diagram, goal)) = data.rsplit_once("|-") else {
+            return Err(Error::Syntax {
+                range: data.len()..data.len(),
+                message: "Expected `<diagram> |- <goal>`".into(),
+                suggestion: None,
+            });
+        };
+        let faces = if diagram.trim().is_empty() {vec![]} else {self.parse_faces(diagram)?};
+        let goal = self.parse(goal).map_err(|err| err.offset(diagram.len() + 2))?;
+        Ok((faces, goal))
+    }
+}
+
 /// Parses an expression string.
-pub fn parse_str(data: &str) -> Result<Expr, String> {
-    use piston_meta::{parse_errstr, syntax_errstr};
+pub fn parse_str(data: &str) -> Result<Expr, Error> {
+    Parser::shared()?.parse(data)
+}
 
-    let syntax_src = include_str!("../assets/syntax.txt");
-    let syntax = syntax_errstr(syntax_src)?;
+// Finds unbalanced brackets.
+fn brackets(data: &str) -> Option<Error> {
+    let mut stack = vec![];
+    for (i, c) in data.char_indices() {
+        match c {
+            '(' | '[' => stack.push((i, c)),
+            ')' | ']' => {
+                let open = if c == ')' {'('} else {'['};
+                match stack.pop() {
+                    Some((_, x)) if x == open => {}
+                    Some((j, x)) => return Some(Error::Syntax {
+                        range: i..i + 1,
+                        message: format!("Expected `{}` to close `{}` at {}",
+                            if x == '(' {')'} else {']'}, x, j),
+                        suggestion: None,
+                    }),
+                    None => return Some(Error::Syntax {
+                        range: i..i + 1,
+                        message: format!("Unexpected `{}`", c),
+                        suggestion: None,
+                    }),
+                }
+            }
+            _ => {}
+        }
+    }
+    stack.pop().map(|(i, c)| Error::Syntax {
+        range: i..i + 1,
+        message: format!("Unclosed `{}`", c),
+        suggestion: None,
+    })
+}
+
+/// Returns every arrow in ASCII notation, in the order of [Morphism] and level.
+pub fn arrows() -> Vec<String> {
+    let mut res: Vec<String> = vec![];
+    let xy = Arc::new((Obj(Arc::new("X".into())), Obj(Arc::new("Y".into()))));
+    for n in 1..=3 {
+        for m in [
+            Dir, RevDir, Epi, RevEpi, Mono, RevMono, RightInv, RevRightInv,
+            EpiMono, RevEpiMono, Iso, Zero,
+        ] {
+            let s = format!("{}", Mor(m, n, xy.clone()));
+            let arrow = s[2..s.len() - 2].to_string();
+            if !res.contains(&arrow) {res.push(arrow)}
+        }
+    }
+    res
+}
 
-    let mut meta_data = vec![];
-    parse_errstr(&syntax, &data, &mut meta_data)?;
+// Computes number of edits to turn one string into another.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, &y) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = (prev + (x != y) as usize).min(row[j] + 1).min(cur + 1);
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
 
-    // piston_meta::json::print(&meta_data);
+// Finds unknown arrow at or right before some offset, suggesting a similar arrow.
+//
+// Known arrows without whitespace to objects, e.g. `A<->B`, suggest adding whitespace.
+fn arrow(data: &str, offset: usize) -> Option<Error> {
+    let is_word = |c: char| !c.is_whitespace() && !"()[];:∘".contains(c);
+    // Arrows end at characters of object names.
+    let is_token = |c: char| is_word(c) && !c.is_alphanumeric();
+    let offset = offset.min(data.len());
+    let start = data[..offset].char_indices().rev()
+        .take_while(|&(_, c)| is_token(c))
+        .last().map(|(i, _)| i).unwrap_or(offset);
+    let end = data[offset..].char_indices()
+        .find(|&(_, c)| !is_token(c))
+        .map(|(i, _)| offset + i).unwrap_or(data.len());
+    let token = &data[start..end];
+    if !token.chars().any(|c| "-=<>!".contains(c)) {return None}
 
-    let convert = Convert::new(&meta_data);
-    let mut ignored = vec![];
-    match parse_expr("expr", convert, &mut ignored) {
-        Err(()) => Err("Could not convert meta data".into()),
-        Ok((_, expr)) => Ok(expr),
+    let arrows = arrows();
+    if arrows.iter().any(|a| a == token) {
+        let before = data[..start].char_indices().rev()
+            .take_while(|&(_, c)| is_word(c))
+            .last().map(|(i, _)| i).unwrap_or(start);
+        let after = data[end..].char_indices()
+            .find(|&(_, c)| !is_word(c))
+            .map(|(i, _)| end + i).unwrap_or(data.len());
+        if (before, after) == (start, end) {return None}
+        let spaced = format!("{} {} {}", &data[before..start], token, &data[end..after]);
+        return Some(Error::Syntax {
+            range: start..end,
+            message: format!("Expected whitespace around arrow `{}`", token),
+            suggestion: Some(spaced.trim().into()),
+        });
     }
+    let best = arrows.into_iter()
+        .map(|a| (distance(token, &a), a))
+        .min_by_key(|(d, _)| *d)
+        .filter(|(d, _)| *d <= 2);
+    Some(Error::Syntax {
+        range: start..end,
+        message: format!("Unknown arrow `{}`", token),
+        suggestion: best.map(|(_, a)| a),
+    })
 }
@@ -0,0 +1,223 @@
+//! Proof traces for solver results.
+//!
+//! The Avalog solver does not record why it added a fact.
+//! Instead, a derivation is reconstructed after solving,
+//! by finding the rule and the earlier fact that produced each new fact.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use avalog::Expr::*;
+
+use crate::code::Knowledge;
+use crate::sym::Sym;
+use crate::{Expr, Morphism};
+
+/// A fact or rule used by the solver.
+pub type Fact = avalog::Expr<Sym>;
+
+/// Explains why a fact holds.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Derivation {
+    /// A fact constructed from an edge of the diagram.
+    Premise(Fact),
+    /// A fact stated in the rule base.
+    Axiom(Fact),
+    /// A fact inferred by applying a rule to other facts.
+    Rule {
+        /// The rule from the rule base.
+        rule: Fact,
+        /// The inferred fact.
+        conclusion: Fact,
+        /// Derivations of the facts matched by the rule, in order of arguments.
+        premises: Vec<Derivation>,
+    },
+    /// A fact that could not be traced back to any rule.
+    Unexplained(Fact),
+}
+
+impl Derivation {
+    /// Returns the fact being derived.
+    pub fn fact(&self) -> &Fact {
+        match self {
+            Derivation::Premise(x) |
+            Derivation::Axiom(x) |
+            Derivation::Unexplained(x) => x,
+            Derivation::Rule {conclusion, ..} => conclusion,
+        }
+    }
+
+    fn write(&self, w: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        for _ in 0..indent {write!(w, "  ")?}
+        match self {
+            Derivation::Premise(x) => writeln!(w, "{} [premise]", x)?,
+            Derivation::Axiom(x) => writeln!(w, "{} [axiom]", x)?,
+            Derivation::Unexplained(x) => writeln!(w, "{} [unexplained]", x)?,
+            Derivation::Rule {rule, conclusion, premises} => {
+                writeln!(w, "{} by {}", conclusion, rule)?;
+                for p in premises {p.write(w, indent + 1)?}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Derivation {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> fmt::Result {self.write(w, 0)}
+}
+
+/// Explains how an edge of a diagram was upgraded by the solver.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explanation {
+    /// The index of the edge.
+    pub edge: usize,
+    /// The morphism before solving.
+    pub from: Morphism,
+    /// The morphism after solving.
+    pub to: Morphism,
+    /// Each upgrade of the morphism, in the order they happened.
+    pub steps: Vec<Step>,
+}
+
+/// A single upgrade of an edge's morphism.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    /// The morphism before this step.
+    pub from: Morphism,
+    /// The morphism after this step.
+    pub to: Morphism,
+    /// Why the fact causing the upgrade holds.
+    pub derivation: Derivation,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(w, "{:?} => {:?}", self.from, self.to)?;
+        for step in &self.steps {
+            writeln!(w, "  {:?} => {:?}", step.from, step.to)?;
+            step.derivation.write(w, 2)?;
+        }
+        Ok(())
+    }
+}
+
+/// Answers whether a morphism follows from a diagram, see [Solver::query](crate::code::Solver::query).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Answer {
+    /// The queried morphism, e.g. `C <-> D`.
+    pub goal: Expr,
+    /// Derivations of the relations describing the morphism, e.g. `(C, iso'(D))` for `C <-> D`.
+    ///
+    /// This is `None` when some relation does not follow from the diagram.
+    pub derivations: Option<Vec<Derivation>>,
+}
+
+impl Answer {
+    /// Returns `true` if the queried morphism follows from the diagram.
+    pub fn holds(&self) -> bool {self.derivations.is_some()}
+}
+
+impl fmt::Display for Answer {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.derivations {
+            Some(derivations) => {
+                writeln!(w, "yes: {}", self.goal)?;
+                for d in derivations {d.write(w, 1)?}
+                Ok(())
+            }
+            None => writeln!(w, "no: {}", self.goal),
+        }
+    }
+}
+
+/// Reconstructs derivations from the facts found by the solver.
+pub struct Tracer<'a> {
+    knowledge: &'a Knowledge,
+    cache: HashMap<usize, Derivation>,
+}
+
+impl<'a> Tracer<'a> {
+    /// Creates a new tracer.
+    pub fn new(knowledge: &'a Knowledge) -> Tracer<'a> {
+        Tracer {knowledge, cache: HashMap::new()}
+    }
+
+    /// Derives the fact at some index of the solver result.
+    pub fn derive(&mut self, ind: usize) -> Derivation {
+        if let Some(x) = self.cache.get(&ind) {return x.clone()};
+
+        let facts = &self.knowledge.facts;
+        let res = if ind < self.knowledge.axioms {
+            Derivation::Axiom(facts[ind].clone())
+        } else if ind < self.knowledge.premises {
+            Derivation::Premise(facts[ind].clone())
+        } else if let Some((rule, premises)) = self.unfold(ind) {
+            let premises = premises.into_iter().map(|i| self.derive(i)).collect();
+            Derivation::Rule {rule, conclusion: facts[ind].clone(), premises}
+        } else {
+            Derivation::Unexplained(facts[ind].clone())
+        };
+        self.cache.insert(ind, res.clone());
+        res
+    }
+
+    // Finds the original rule and the indices of facts it was applied to.
+    fn unfold(&self, ind: usize) -> Option<(Fact, Vec<usize>)> {
+        let facts = &self.knowledge.facts;
+        let (r, f) = justify(facts, ind)?;
+        if r < self.knowledge.premises {
+            Some((facts[r].clone(), vec![f]))
+        } else {
+            let (rule, mut premises) = self.unfold(r)?;
+            premises.push(f);
+            Some((rule, premises))
+        }
+    }
+}
+
+// Finds a rule and a fact, both preceding the fact at `ind`, that produce it.
+fn justify(facts: &[Fact], ind: usize) -> Option<(usize, usize)> {
+    let target = &facts[ind];
+    let arity = if let Rule(_, args) = target {args.len() + 1} else {1};
+    for (r, rule) in facts[..ind].iter().enumerate() {
+        if let Rule(res, args) = rule {
+            if args.len() != arity {continue}
+            // Filter out rules with heads that can not produce the target.
+            if arity == 1 && !avalog::bind(res, target, &mut vec![], &mut vec![]) {continue}
+
+            for (f, fact) in facts[..ind].iter().enumerate() {
+                if let Rule(_, _) = fact {continue}
+                if match_rule(rule, fact).as_ref() == Some(target) {return Some((r, f))}
+            }
+        }
+    }
+    None
+}
+
+// Applies a rule to a fact the same way as the Avalog solver.
+fn match_rule(rule: &Fact, fact: &Fact) -> Option<Fact> {
+    if let Rule(res, args) = rule {
+        let mut vs = vec![];
+        if !avalog::bind(&args[0], fact, &mut vs, &mut vec![]) {return None};
+        let res = substitute(res, &vs)?;
+        if args.len() > 1 {
+            let args = args[1..].iter().map(|a| substitute(a, &vs)).collect::<Option<_>>()?;
+            Some(Rule(Box::new(res), args))
+        } else {
+            Some(res)
+        }
+    } else {
+        None
+    }
+}
+
+fn substitute(e: &Fact, vs: &[(Arc<String>, Fact)]) -> Option<Fact> {
+    Some(match e {
+        Rel(a, b) => avalog::rel(substitute(a, vs)?, substitute(b, vs)?),
+        Ava(a, b) => avalog::ava(substitute(a, vs)?, substitute(b, vs)?),
+        Var(a) => vs.iter().find(|v| &v.0 == a).map(|v| v.1.clone()).unwrap_or_else(|| e.clone()),
+        Sym(_) => e.clone(),
+        _ => return None,
+    })
+}
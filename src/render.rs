@@ -0,0 +1,116 @@
+//! Terminal rendering of squares as box diagrams.
+//!
+//! For example, `(A <-> B)[(A <-> C) -> (B -> D)] <=> (C -> D)` is drawn as:
+//!
+//! ```text
+//! A ──iso──→ C
+//! │          │
+//! iso        │
+//! ↓          ↓
+//! B ───────→ D
+//! ```
+//!
+//! Arrows are annotated by morphism kind, while plain morphisms have no annotation.
+//! Named morphisms are annotated by name, e.g. `f:iso`.
+//! Edges that are objects are drawn without heads and annotated by the object.
+
+use crate::code::{is_reversed, Square};
+use crate::error::Shape;
+use crate::{Error, Morphism};
+use crate::Morphism::*;
+
+/// Characters used to draw a square.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Style {
+    /// Horizontal line.
+    pub horizontal: char,
+    /// Vertical line.
+    pub vertical: char,
+    /// Heads pointing `[left, right, up, down]`.
+    pub heads: [char; 4],
+}
+
+/// Draws using ASCII characters.
+pub const ASCII: Style = Style {horizontal: '-', vertical: '|', heads: ['<', '>', '^', 'v']};
+
+/// Draws using Unicode box-drawing characters and arrows.
+pub const UNICODE: Style = Style {horizontal: '─', vertical: '│', heads: ['←', '→', '↑', '↓']};
+
+// Returns annotation of morphism.
+fn kind(code: Morphism) -> &'static str {
+    match code {
+        Unknown | Dir | RevDir => "",
+        Mono | RevMono => "mono",
+        Epi | RevEpi => "epi",
+        EpiMono | RevEpiMono => "epi-mono",
+        RightInv | RevRightInv => "right-inv",
+        Iso | RevIso => "iso",
+        Zero | RevZero => "zero",
+    }
+}
+
+// Draws horizontal arrow of some width.
+fn horizontal(style: &Style, label: &str, code: Morphism, width: usize) -> String {
+    let head = if code == Unknown {None}
+        else if is_reversed(code) {Some(style.heads[0])}
+        else {Some(style.heads[1])};
+    let n = label.chars().count();
+    let line = width - n - head.is_some() as usize;
+    let left = line / 2;
+    let mut res = String::new();
+    if is_reversed(code) {res.extend(head)}
+    res.extend(std::iter::repeat_n(style.horizontal, left));
+    res.push_str(label);
+    res.extend(std::iter::repeat_n(style.horizontal, line - left));
+    if !is_reversed(code) {res.extend(head)}
+    res
+}
+
+/// Draws square as a box diagram.
+pub fn square(sq: &Square, style: &Style) -> Result<String, Error> {
+    let corners = sq.corners().ok_or(Error::Shape(Shape::Disconnected))?;
+    let labels: Vec<String> = sq.labels.iter().zip(&sq.code).zip(&sq.names).map(|((l, &code), name)| {
+        match (l, name) {
+            ([0, _, _], Some(name)) if kind(code).is_empty() => name.to_string(),
+            ([0, _, _], Some(name)) => format!("{}:{}", name, kind(code)),
+            ([0, _, _], None) => kind(code).into(),
+            ([obj, _, _], _) => sq.bind[(*obj - 1) as usize].to_string(),
+        }
+    }).collect();
+    let len = |s: &str| s.chars().count();
+    let [a, b, c, d] = corners.map(|x| x.to_string());
+
+    let left_width = len(&a).max(len(&b));
+    // Leave room for the label of the left edge and at least two lines around labels.
+    let width = len(&labels[1]).max(len(&labels[2])).max(len(&labels[0]).saturating_sub(left_width)) + 5;
+    let right = left_width + width + 2;
+
+    let pad = |s: &str, n: usize| format!("{}{}", s, " ".repeat(n - len(s)));
+    let row = |x: String, y: String| format!("{}{}", pad(&x, right), y).trim_end().to_string() + "\n";
+    // Draws a row of the vertical edges, using a head when `head` matches direction.
+    let vertical = |head: bool| -> [String; 2] {
+        [0, 3].map(|i| {
+            let code = sq.code[i];
+            match (head, code == Unknown, is_reversed(code)) {
+                (_, true, _) => style.vertical,
+                (true, _, false) => style.heads[3],
+                (false, _, true) => style.heads[2],
+                _ => style.vertical,
+            }.to_string()
+        })
+    };
+
+    let mut res = String::new();
+    res.push_str(&row(format!("{} {}", pad(&a, left_width), horizontal(style, &labels[1], sq.code[1], width)), c));
+    let [l, r] = vertical(false);
+    res.push_str(&row(l, r));
+    if !labels[0].is_empty() || !labels[3].is_empty() {
+        let l = if labels[0].is_empty() {style.vertical.to_string()} else {labels[0].clone()};
+        let r = if labels[3].is_empty() {style.vertical.to_string()} else {labels[3].clone()};
+        res.push_str(&row(l, r));
+    }
+    let [l, r] = vertical(true);
+    res.push_str(&row(l, r));
+    res.push_str(&row(format!("{} {}", pad(&b, left_width), horizontal(style, &labels[2], sq.code[2], width)), d));
+    Ok(res)
+}
@@ -0,0 +1,194 @@
+//! Sessions accumulating hypotheses across inputs.
+//!
+//! A hypothesis is an edge, e.g. `A !-> B`, `A => B` or `A -> B -> C`,
+//! or a face of a solved diagram.
+//! Hypotheses are added to every diagram solved or queried in the session,
+//! such that knowledge established by one input is used by the next.
+//!
+//! A session is saved to a file as one hypothesis per line.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::code::{reverse, Diagram, Edges, Solution, Solver};
+use crate::parsing::Parser;
+use crate::proof::{Answer, Explanation};
+use crate::{Error, Expr};
+
+/// Stores hypotheses across inputs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Session {
+    /// Hypotheses, in the order they were assumed.
+    pub hypotheses: Vec<Expr>,
+}
+
+// Returns `true` if hypothesis is a face instead of an edge, e.g. `A => B` or `A -> B -> C`.
+fn is_face(e: &Expr) -> bool {
+    match e {
+        Expr::Path(_) => true,
+        Expr::Mor(_, _, ab) => matches!(ab.0, Expr::Path(_)) || matches!(ab.1, Expr::Path(_)),
+        _ => false,
+    }
+}
+
+// Writes reversed iso and zero edges as iso and zero in the other direction,
+// since they are displayed like iso and zero and would be loaded in the wrong direction.
+fn normalize(e: &mut Expr) {
+    use crate::Morphism::{RevIso, RevZero};
+
+    match e {
+        Expr::Mor(mor @ (RevIso | RevZero), _, ab) => {
+            *mor = reverse(*mor);
+            let ab = Arc::make_mut(ab);
+            std::mem::swap(&mut ab.0, &mut ab.1);
+            normalize(&mut ab.0);
+            normalize(&mut ab.1);
+        }
+        Expr::Mor(_, _, ab) | Expr::Path(ab) => {
+            let ab = Arc::make_mut(ab);
+            normalize(&mut ab.0);
+            normalize(&mut ab.1);
+        }
+        Expr::Named(_, a) => normalize(Arc::make_mut(a)),
+        _ => {}
+    }
+}
+
+// Reports contradictions using the index of the face in the input,
+// given the number of faces from hypotheses.
+//
+// Edges of hypotheses are left out of derived contradictions.
+fn shift(err: Error, n: usize) -> Error {
+    match err {
+        Error::Contradiction {face, edges} if face >= n =>
+            Error::Contradiction {face: face - n, edges},
+        Error::Inconsistent {fact, edges} => Error::Inconsistent {
+            fact,
+            edges: edges.into_iter().filter(|&(face, _)| face >= n).map(|(face, i)| (face - n, i)).collect(),
+        },
+        err => err,
+    }
+}
+
+impl Session {
+    /// Creates a new session without hypotheses.
+    pub fn new() -> Session {Session::default()}
+
+    /// Assumes edges, e.g. `A !-> B`, `A => B` or `A -> B -> C`,
+    /// or faces of a diagram, separated by `;`.
+    ///
+    /// Faces are solved using the current hypotheses before they are added.
+    /// Reversed iso and zero edges are stored in the other direction, see [Session::save].
+    /// Returns the new hypotheses.
+    pub fn assume(&mut self, solver: &Solver, a: &str) -> Result<Vec<Expr>, Error> {
+        let (mors, faces): (Vec<Expr>, Vec<Expr>) = Parser::shared()?.parse_faces(a)?
+            .into_iter().partition(|e| !is_face(e));
+        let n = self.hypotheses.len();
+        self.hypotheses.extend(mors);
+        // Morphisms are checked by creating the diagram, e.g. compositions that do not meet.
+        let res = if faces.is_empty() {
+            self.diagram(vec![]).map(|_| vec![])
+        } else {
+            self.solve_faces(solver, faces).map(|x| x.faces)
+        };
+        match res {
+            Ok(x) => self.hypotheses.extend(x),
+            Err(err) => {
+                self.hypotheses.truncate(n);
+                return Err(err);
+            }
+        }
+        for e in &mut self.hypotheses[n..] {normalize(e)}
+        Ok(self.hypotheses[n..].to_vec())
+    }
+
+    /// Removes the hypothesis at some index, returning it.
+    pub fn retract(&mut self, ind: usize) -> Option<Expr> {
+        if ind < self.hypotheses.len() {Some(self.hypotheses.remove(ind))} else {None}
+    }
+
+    /// Solves a string using the hypotheses, see [Solver::solve].
+    ///
+    /// The solution only contains the faces of the input.
+    pub fn solve(&self, solver: &Solver, a: &str) -> Result<Solution, Error> {
+        self.solve_faces(solver, Parser::shared()?.parse_faces(a)?)
+    }
+
+    /// Asks whether a morphism follows from a diagram and the hypotheses, see [Solver::query].
+    ///
+    /// The diagram may be empty, e.g. `|- A -> C`, to query the hypotheses alone.
+    pub fn query_str(&self, solver: &Solver, a: &str) -> Result<Answer, Error> {
+        let (faces, goal) = Parser::shared()?.parse_query(a)?;
+        solver.check_strict(&Diagram::new(&faces)?, &faces)?;
+        let (d, _, n) = self.diagram(faces)?;
+        solver.query_diagram(&d, &goal).map_err(|err| shift(err, n))
+    }
+
+    /// Explains why the solver upgraded edges of a diagram using the hypotheses, see [Solver::explain].
+    ///
+    /// Returns the diagram and the number of faces from hypotheses, see [Session::diagram],
+    /// together with the explanations, which refer to edges of the diagram.
+    pub fn explain(&self, solver: &Solver, a: &str) -> Result<(Diagram, usize, Vec<Explanation>), Error> {
+        let faces = Parser::shared()?.parse_faces(a)?;
+        solver.check_strict(&Diagram::new(&faces)?, &faces)?;
+        let (d, _, n) = self.diagram(faces)?;
+        let explanations = solver.explain(&d).map_err(|err| shift(err, n))?;
+        Ok((d, n, explanations))
+    }
+
+    // Solves faces, leaving out faces from hypotheses in the solution.
+    fn solve_faces(&self, solver: &Solver, faces: Vec<Expr>) -> Result<Solution, Error> {
+        // Check input before hypotheses are added, to report the index of the face in the input.
+        solver.check_strict(&Diagram::new(&faces)?, &faces)?;
+        let (d, faces, n) = self.diagram(faces)?;
+        let mut res = solver.solve_diagram(&d, faces).map_err(|err| shift(err, n))?;
+        res.faces.drain(..n);
+        res.edges.retain(|e| e.face >= n);
+        for e in &mut res.edges {e.face -= n}
+        res.corrections.retain(|c| c.face >= n);
+        for c in &mut res.corrections {c.face -= n}
+        Ok(res)
+    }
+
+    /// Creates a diagram from hypotheses and faces.
+    ///
+    /// Returns the diagram, its faces and the number of faces from hypotheses,
+    /// which come before the other faces.
+    pub fn diagram(&self, faces: Vec<Expr>) -> Result<(Diagram, Vec<Expr>, usize), Error> {
+        let mut all: Vec<Expr> = self.hypotheses.iter().filter(|e| is_face(e)).cloned().collect();
+        let n = all.len();
+        all.extend(faces);
+        let mut d = Diagram::new(&all)?;
+        for e in self.hypotheses.iter().filter(|e| !is_face(e)) {d.assume(e)?;}
+        d.check().map_err(|err| shift(err, n))?;
+        Ok((d, all, n))
+    }
+
+    /// Saves hypotheses to a file, one per line.
+    ///
+    /// Reversed iso and zero edges are written in the other direction,
+    /// such that loading the file gives the same knowledge.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let data: String = self.hypotheses.iter().map(|e| {
+            let mut e = e.clone();
+            normalize(&mut e);
+            format!("{}\n", e)
+        }).collect();
+        std::fs::write(&path, data)
+            .map_err(|err| Error::Io(format!("{}: {}", path.as_ref().display(), err)))
+    }
+
+    /// Loads hypotheses from a file, one per line.
+    ///
+    /// Empty lines and lines starting with `//` are ignored.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Session, Error> {
+        let data = std::fs::read_to_string(&path)
+            .map_err(|err| Error::Io(format!("{}: {}", path.as_ref().display(), err)))?;
+        let parser = Parser::shared()?;
+        let mut hypotheses = vec![];
+        for line in data.lines().map(|x| x.trim()).filter(|x| !x.is_empty() && !x.starts_with("//")) {
+            hypotheses.extend(parser.parse_faces(line)?);
+        }
+        Ok(Session {hypotheses})
+    }
+}
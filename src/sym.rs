@@ -20,10 +20,12 @@ impl fmt::Display for Sym {
     }
 }
 
+/// Numbers in rules are objects, e.g. `1`, such that rules can refer to them.
 impl From<Arc<String>> for Sym {
     fn from(val: Arc<String>) -> Sym {
         match &**val {
             "0" => Sym::Expr(Expr::_0),
+            x if x.chars().all(|c| c.is_ascii_digit()) => Sym::Expr(Expr::Obj(val)),
             _ => Sym::Ava(val),
         }
     }
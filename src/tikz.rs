@@ -0,0 +1,104 @@
+//! LaTeX `tikz-cd` export.
+//!
+//! Objects are placed in a matrix:
+//!
+//! ```text
+//! A & C \\
+//! B & D
+//! ```
+//!
+//! The left edge goes from `A` to `B`, the top edge from `A` to `C`,
+//! the bottom edge from `B` to `D` and the right edge from `C` to `D`.
+
+use crate::code::Square;
+use crate::error::Shape;
+use crate::{Error, Expr, Morphism};
+use crate::Morphism::*;
+
+// Corners connected by each edge, in the order of the square.
+const EDGES: [(usize, usize); 4] = [(0, 1), (0, 2), (1, 3), (2, 3)];
+
+// Returns tikz-cd options describing the style of a morphism.
+fn style(code: Morphism) -> &'static str {
+    match code {
+        Unknown | Dir | RevDir | Iso | RevIso | Zero | RevZero => "",
+        Mono | RevMono => ", hook",
+        Epi | RevEpi | RightInv | RevRightInv => ", two heads",
+        EpiMono | RevEpiMono => ", hook, two heads",
+    }
+}
+
+// Returns the label marking a morphism, if any.
+fn mark(code: Morphism) -> Option<&'static str> {
+    match code {
+        RightInv | RevRightInv => Some("\\mathrm{split}"),
+        Iso | RevIso => Some("\\sim"),
+        Zero | RevZero => Some("0"),
+        _ => None,
+    }
+}
+
+// Escapes characters of names that have a meaning in LaTeX or tikz-cd.
+fn escape(a: &str) -> String {
+    let mut res = String::new();
+    for c in a.chars() {
+        match c {
+            '\\' => res.push_str("\\backslash{}"),
+            '^' => res.push_str("\\hat{}"),
+            '~' => res.push_str("\\sim{}"),
+            // Braces keep tikz-cd from ending the label.
+            '"' => res.push_str("{\"}"),
+            '{' | '}' | '_' | '#' | '%' | '&' | '$' => {
+                res.push('\\');
+                res.push(c);
+            }
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Exports square as a `tikzcd` environment.
+///
+/// Edges that are objects, e.g. `f` in `f[(A -> C) -> (B -> D)] <=> (C -> D)`,
+/// are drawn as arrows labelled by the object.
+/// Named morphisms, e.g. `f : A -> B`, are labelled by their name.
+/// Isomorphisms, zero morphisms and split epimorphisms are marked by `\sim`, `0` and `\mathrm{split}`,
+/// after the name in the same label if there is one.
+/// Names are escaped, e.g. `f_1` is written `f\_1`.
+pub fn square(sq: &Square) -> Result<String, Error> {
+    use crate::code::is_reversed;
+
+    let corners = sq.corners().ok_or(Error::Shape(Shape::Disconnected))?;
+    // Arrows starting in each cell.
+    let mut arrows = [String::new(), String::new(), String::new(), String::new()];
+    for (i, (&(a, b), &code)) in EDGES.iter().zip(&sq.code).enumerate() {
+        let (from, to) = if is_reversed(code) {(b, a)} else {(a, b)};
+        let dir = match (from, to) {
+            (0, 1) | (2, 3) => "d",
+            (1, 0) | (3, 2) => "u",
+            (0, 2) | (1, 3) => "r",
+            _ => "l",
+        };
+        let name = match (sq.labels[i], &sq.names[i]) {
+            ([obj, 0, 0], _) if obj != 0 => Some(escape(&sq.bind[(obj - 1) as usize].to_string())),
+            (_, Some(name)) => Some(escape(name)),
+            _ => None,
+        };
+        let label = match (name, mark(code)) {
+            (Some(name), Some(mark)) => format!(", \"{}\\,{}\"", name, mark),
+            (Some(x), None) => format!(", \"{}\"", x),
+            (None, Some(x)) => format!(", \"{}\"", x),
+            (None, None) => String::new(),
+        };
+        arrows[from].push_str(&format!(" \\arrow[{}{}{}]", dir, style(code), label));
+    }
+    let cell = |i: usize| format!("{}{}", escape(&corners[i].to_string()), arrows[i]);
+    Ok(format!("\\begin{{tikzcd}}\n{} & {} \\\\\n{} & {}\n\\end{{tikzcd}}\n",
+        cell(0), cell(2), cell(1), cell(3)))
+}
+
+/// Exports expression of a square as a `tikzcd` environment.
+pub fn expr(e: &Expr) -> Result<String, Error> {
+    square(&Square::new(e)?)
+}